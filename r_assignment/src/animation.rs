@@ -0,0 +1,124 @@
+#![allow(dead_code)]
+
+use crate::quaternion::Quaternion;
+use crate::transform::{scale, translation};
+use nalgebra::{Matrix4, Vector3};
+
+// Pose of an object at `time` seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Keyframe {
+    fn default() -> Self {
+        Self::new(
+            0.,
+            Vector3::zeros(),
+            Quaternion::identity(),
+            Vector3::new(1., 1., 1.),
+        )
+    }
+}
+
+impl Keyframe {
+    pub fn new(
+        time: f32,
+        translation: Vector3<f32>,
+        rotation: Quaternion,
+        scale: Vector3<f32>,
+    ) -> Self {
+        Self {
+            time,
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // Scale, then rotate, then translate
+    pub fn model(&self) -> Matrix4<f32> {
+        translation(&self.translation) * self.rotation.to_matrix() * scale(&self.scale)
+    }
+}
+
+// Keyframes ordered by time. Translation and scale are interpolated linearly
+// and rotation with slerp, so the object turns at a constant speed between
+// two keyframes. Before the first and after the last keyframe the pose is
+// held, unless `looping` wraps the time around.
+#[derive(Debug, Clone, Default)]
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+    pub looping: bool,
+}
+
+impl Animation {
+    pub fn new(looping: bool) -> Self {
+        Self {
+            keyframes: Vec::new(),
+            looping,
+        }
+    }
+
+    // Replaces a keyframe at the same time
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|k| k.time.total_cmp(&keyframe.time))
+        {
+            Ok(i) => self.keyframes[i] = keyframe,
+            Err(i) => self.keyframes.insert(i, keyframe),
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.,
+        }
+    }
+
+    // Interpolated pose at `time`, the default pose without keyframes
+    pub fn sample(&self, time: f32) -> Keyframe {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return Keyframe {
+                time,
+                ..Keyframe::default()
+            };
+        };
+        let duration = self.duration();
+        let local = if self.looping && duration > 0. {
+            first.time + (time - first.time).rem_euclid(duration)
+        } else {
+            time.clamp(first.time, last.time)
+        };
+
+        let next = self.keyframes.partition_point(|k| k.time <= local);
+        let pose = if next == 0 {
+            *first
+        } else if next == self.keyframes.len() {
+            *last
+        } else {
+            let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+            let t = (local - a.time) / (b.time - a.time);
+            Keyframe {
+                time: local,
+                translation: a.translation.lerp(&b.translation, t),
+                rotation: a.rotation.slerp(&b.rotation, t),
+                scale: a.scale.lerp(&b.scale, t),
+            }
+        };
+        Keyframe { time, ..pose }
+    }
+
+    pub fn model(&self, time: f32) -> Matrix4<f32> {
+        self.sample(time).model()
+    }
+}
@@ -0,0 +1,306 @@
+#![allow(dead_code)]
+
+use crate::mesh::Mesh;
+use nalgebra::{Vector2, Vector3};
+
+// Upper bound on the segments of a patch edge
+const MAX_SEGMENTS: usize = 64;
+
+// Bicubic Bezier patch, control point `row * 4 + col` weights u along the
+// columns and v along the rows
+#[derive(Debug, Clone)]
+pub struct BezierPatch {
+    pub control_points: [Vector3<f32>; 16],
+}
+
+impl BezierPatch {
+    pub fn new(control_points: [Vector3<f32>; 16]) -> Self {
+        Self { control_points }
+    }
+
+    pub fn evaluate(&self, u: f32, v: f32) -> Vector3<f32> {
+        self.blend(&bernstein(u), &bernstein(v))
+    }
+
+    // Partial derivatives along u and v
+    pub fn derivatives(&self, u: f32, v: f32) -> (Vector3<f32>, Vector3<f32>) {
+        let du = self.blend(&bernstein_derivative(u), &bernstein(v));
+        let dv = self.blend(&bernstein(u), &bernstein_derivative(v));
+        (du, dv)
+    }
+
+    // du x dv, so triangles wound along +u then +v face it. Patches collapsed
+    // to a point along an edge, like the top of the teapot lid, take the
+    // normal from just inside the patch.
+    pub fn normal(&self, u: f32, v: f32) -> Vector3<f32> {
+        let (du, dv) = self.derivatives(u, v);
+        if let Some(n) = du.cross(&dv).try_normalize(1e-6) {
+            return n;
+        }
+        let (u, v) = (u + (0.5 - u) * 1e-3, v + (0.5 - v) * 1e-3);
+        let (du, dv) = self.derivatives(u, v);
+        du.cross(&dv)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::z)
+    }
+
+    // Triangles with analytic normals and the patch parameters as UVs.
+    // Each direction gets enough segments to stay within `tolerance` of the
+    // surface. The four edges carry exactly their own curve's samples and are
+    // stitched to the interior grid with transition triangles, so
+    // neighbouring patches tessellated separately share every edge vertex
+    // and meet without cracks.
+    pub fn tessellate(&self, tolerance: f32) -> Mesh {
+        let row = |i: usize| [0, 1, 2, 3].map(|j| self.control_points[i * 4 + j]);
+        let col = |j: usize| [0, 1, 2, 3].map(|i| self.control_points[i * 4 + j]);
+        // at least one interior vertex for the edges to stitch to
+        let nu = (0..4)
+            .map(|i| segments(&row(i), tolerance))
+            .fold(2, usize::max);
+        let nv = (0..4)
+            .map(|j| segments(&col(j), tolerance))
+            .fold(2, usize::max);
+
+        let mut mesh = Mesh::default();
+        let vertex = |mesh: &mut Mesh, u: f32, v: f32, position: Vector3<f32>| {
+            mesh.positions.push(position);
+            mesh.normals.push(self.normal(u, v));
+            mesh.tex_coords.push(Vector2::new(u, v));
+            mesh.positions.len() - 1
+        };
+
+        // interior grid, vertex (a, b) for a in 1..nu and b in 1..nv
+        let inner_u = nu - 1;
+        let mut inner = Vec::with_capacity(inner_u * (nv - 1));
+        for b in 1..nv {
+            for a in 1..nu {
+                let (u, v) = (a as f32 / nu as f32, b as f32 / nv as f32);
+                inner.push(vertex(&mut mesh, u, v, self.evaluate(u, v)));
+            }
+        }
+        let at = |a: usize, b: usize| inner[(b - 1) * inner_u + a - 1];
+        for b in 1..nv - 1 {
+            for a in 1..nu - 1 {
+                let quad = [at(a, b), at(a + 1, b), at(a + 1, b + 1), at(a, b + 1)];
+                push_triangle(&mut mesh, [quad[0], quad[1], quad[2]]);
+                push_triangle(&mut mesh, [quad[0], quad[2], quad[3]]);
+            }
+        }
+
+        // each edge with the interior row or column next to it
+        let edges = [
+            (
+                row(0),
+                (1..nu).map(|a| at(a, 1)).collect::<Vec<_>>(),
+                false,
+                0.,
+            ),
+            (row(3), (1..nu).map(|a| at(a, nv - 1)).collect(), false, 1.),
+            (col(0), (1..nv).map(|b| at(1, b)).collect(), true, 0.),
+            (col(3), (1..nv).map(|b| at(nu - 1, b)).collect(), true, 1.),
+        ];
+        for (curve, side, along_v, fixed) in edges {
+            let samples = edge_samples(&curve, tolerance);
+            let n = samples.len() - 1;
+            let outer: Vec<usize> = samples
+                .into_iter()
+                .enumerate()
+                .map(|(k, p)| {
+                    let t = k as f32 / n as f32;
+                    let (u, v) = if along_v { (fixed, t) } else { (t, fixed) };
+                    vertex(&mut mesh, u, v, p)
+                })
+                .collect();
+            let param = |mesh: &Mesh, i: usize| {
+                let uv = mesh.tex_coords[i];
+                if along_v {
+                    uv.y
+                } else {
+                    uv.x
+                }
+            };
+            // advance along whichever side has the nearer next midpoint
+            let (mut i, mut j) = (0, 0);
+            while i + 1 < outer.len() || j + 1 < side.len() {
+                let outer_next = outer
+                    .get(i + 1)
+                    .map(|&o| param(&mesh, outer[i]) + param(&mesh, o));
+                let side_next = side
+                    .get(j + 1)
+                    .map(|&s| param(&mesh, side[j]) + param(&mesh, s));
+                let advance_outer = match (outer_next, side_next) {
+                    (Some(o), Some(s)) => o <= s,
+                    (o, _) => o.is_some(),
+                };
+                if advance_outer {
+                    push_triangle(&mut mesh, [outer[i], outer[i + 1], side[j]]);
+                    i += 1;
+                } else {
+                    push_triangle(&mut mesh, [outer[i], side[j + 1], side[j]]);
+                    j += 1;
+                }
+            }
+        }
+        mesh
+    }
+
+    fn blend(&self, bu: &[f32; 4], bv: &[f32; 4]) -> Vector3<f32> {
+        self.control_points
+            .iter()
+            .enumerate()
+            .map(|(k, p)| p * (bv[k / 4] * bu[k % 4]))
+            .sum()
+    }
+}
+
+// All patches in a single mesh, vertices along shared edges are duplicated
+pub fn tessellate(patches: &[BezierPatch], tolerance: f32) -> Mesh {
+    let mut mesh = Mesh::default();
+    for patch in patches {
+        let part = patch.tessellate(tolerance);
+        let offset = mesh.positions.len() as i32;
+        mesh.positions.extend(part.positions);
+        mesh.normals.extend(part.normals);
+        mesh.tex_coords.extend(part.tex_coords);
+        mesh.indices
+            .extend(part.indices.iter().map(|f| f.add_scalar(offset)));
+    }
+    mesh
+}
+
+// Newell's teapot format: the patch count, one line of 16 one-based control
+// point indices per patch, the vertex count and one `x, y, z` line per vertex.
+// Values may be separated by commas or whitespace.
+pub fn parse_patches(text: &str) -> Result<Vec<BezierPatch>, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    let mut next_values = || -> Result<Vec<&str>, String> {
+        let line = lines.next().ok_or("Unexpected end of patch data")?;
+        Ok(line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .collect())
+    };
+    let count = |values: Vec<&str>| -> Result<usize, String> {
+        match values[..] {
+            [n] => n.parse().map_err(|_| format!("Invalid count: {n}")),
+            _ => Err("Expected a single count".to_string()),
+        }
+    };
+
+    let patch_count = count(next_values()?)?;
+    let mut indices = Vec::with_capacity(patch_count);
+    for _ in 0..patch_count {
+        let values = next_values()?;
+        if values.len() != 16 {
+            return Err("Patches need 16 control point indices".to_string());
+        }
+        let patch = values
+            .iter()
+            .map(|s| {
+                s.parse::<usize>()
+                    .map_err(|_| format!("Invalid index: {s}"))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        indices.push(patch);
+    }
+
+    let vertex_count = count(next_values()?)?;
+    let mut vertices = Vec::with_capacity(vertex_count);
+    for _ in 0..vertex_count {
+        let values = next_values()?;
+        let coords = values
+            .iter()
+            .map(|s| {
+                s.parse::<f32>()
+                    .map_err(|_| format!("Invalid coordinate: {s}"))
+            })
+            .collect::<Result<Vec<f32>, String>>()?;
+        match coords[..] {
+            [x, y, z] => vertices.push(Vector3::new(x, y, z)),
+            _ => return Err("Vertices need 3 coordinates".to_string()),
+        }
+    }
+
+    indices
+        .iter()
+        .map(|patch| {
+            let mut control_points = [Vector3::zeros(); 16];
+            for (p, &k) in control_points.iter_mut().zip(patch) {
+                *p = *k
+                    .checked_sub(1)
+                    .and_then(|k| vertices.get(k))
+                    .ok_or("Invalid ind")?;
+            }
+            Ok(BezierPatch::new(control_points))
+        })
+        .collect()
+}
+
+pub fn load_patches(path: &str) -> Result<Vec<BezierPatch>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    parse_patches(&text)
+}
+
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1. - t;
+    [s * s * s, 3. * t * s * s, 3. * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f32) -> [f32; 4] {
+    let s = 1. - t;
+    [
+        -3. * s * s,
+        3. * s * s - 6. * t * s,
+        6. * t * s - 3. * t * t,
+        3. * t * t,
+    ]
+}
+
+fn cubic(points: &[Vector3<f32>; 4], t: f32) -> Vector3<f32> {
+    let b = bernstein(t);
+    (0..4).map(|i| points[i] * b[i]).sum()
+}
+
+// Wang's bound: a cubic split into this many uniform segments deviates from
+// its chords by at most `tolerance`. Symmetric in the point order, so both
+// patches sharing an edge agree on it.
+fn segments(points: &[Vector3<f32>; 4], tolerance: f32) -> usize {
+    let m = (0..2)
+        .map(|i| (points[i] - 2. * points[i + 1] + points[i + 2]).norm())
+        .fold(0., f32::max);
+    let n = (0.75 * m / tolerance.max(f32::EPSILON)).sqrt().ceil();
+    (n as usize).clamp(1, MAX_SEGMENTS)
+}
+
+// Uniform samples of a patch edge, always taken in the same direction along
+// the curve so the patch on the other side of the edge gets bit for bit the
+// same points
+fn edge_samples(points: &[Vector3<f32>; 4], tolerance: f32) -> Vec<Vector3<f32>> {
+    let n = segments(points, tolerance);
+    let reversed = [points[3], points[2], points[1], points[0]];
+    let key = |curve: &[Vector3<f32>; 4]| curve.map(|p| [p.x, p.y, p.z]);
+    let flip = key(&reversed) < key(points);
+    let curve = if flip { &reversed } else { points };
+    let mut samples: Vec<_> = (0..=n).map(|k| cubic(curve, k as f32 / n as f32)).collect();
+    if flip {
+        samples.reverse();
+    }
+    samples
+}
+
+// Wound counter-clockwise in the patch parameters so it faces along the
+// normal, collapsed edges leave zero area triangles behind which are skipped
+fn push_triangle(mesh: &mut Mesh, face: [usize; 3]) {
+    let uv = face.map(|k| mesh.tex_coords[k]);
+    let area = (uv[1] - uv[0]).perp(&(uv[2] - uv[0]));
+    let face = if area < 0. {
+        [face[0], face[2], face[1]]
+    } else {
+        face
+    };
+    let p = face.map(|k| mesh.positions[k]);
+    if (p[1] - p[0]).cross(&(p[2] - p[0])).norm() > f32::EPSILON {
+        mesh.indices
+            .push(Vector3::new(face[0] as i32, face[1] as i32, face[2] as i32));
+    }
+}
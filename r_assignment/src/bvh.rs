@@ -0,0 +1,386 @@
+#![allow(dead_code)]
+
+use crate::ray::{intersect_triangle, Hit, Ray};
+use crate::triangle::Triangle;
+use nalgebra::Vector3;
+
+// Triangles per leaf below which nodes are no longer split
+const MAX_LEAF_SIZE: usize = 4;
+const SAH_BUCKETS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMethod {
+    // Split at the middle of the centroid bounds along the longest axis
+    Midpoint,
+    // Surface area heuristic evaluated over bucketed centroids
+    Sah,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn of_triangle(t: &Triangle) -> Self {
+        let mut aabb = Self::empty();
+        for v in t.v() {
+            aabb.grow(v);
+        }
+        aabb
+    }
+
+    pub fn grow(&mut self, p: &Vector3<f32>) {
+        self.min = self.min.inf(p);
+        self.max = self.max.sup(p);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = (self.max - self.min).map(|c| c.max(0.));
+        2. * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test, entry distance when the ray overlaps (t_min, t_max)
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        inv_dir: &Vector3<f32>,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<f32> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let mut near = (self.min[axis] - ray.origin[axis]) * inv_dir[axis];
+            let mut far = (self.max[axis] - ray.origin[axis]) * inv_dir[axis];
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from 0 * inf leaves the interval untouched
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+        Some(t0)
+    }
+}
+
+enum Node {
+    // `count` entries of `order` starting at `first`
+    Leaf {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    },
+    // the left child directly follows its parent
+    Interior {
+        bounds: Aabb,
+        right: usize,
+        axis: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } => bounds,
+            Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Bounding volume hierarchy over world space triangles, flattened in depth
+// first order
+pub struct Bvh {
+    triangles: Vec<Triangle>,
+    // triangle indices grouped by leaf
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<Triangle>, split: SplitMethod) -> Self {
+        let bounds: Vec<Aabb> = triangles.iter().map(Aabb::of_triangle).collect();
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            build(&bounds, &mut order, 0, split, &mut nodes);
+        }
+        Self {
+            triangles,
+            order,
+            nodes,
+        }
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |node| *node.bounds())
+    }
+
+    // Closest hit, `Hit::triangle` indexes the triangles the BVH was built from
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect_range(ray, 0., f32::INFINITY)
+    }
+
+    // Closest hit with t in (t_min, t_max)
+    pub fn intersect_range(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inv_dir = ray.direction.map(|d| 1. / d);
+        let negative = [inv_dir.x < 0., inv_dir.y < 0., inv_dir.z < 0.];
+
+        let mut closest: Option<(f32, usize, f32, f32)> = None;
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let t_far = closest.map_or(t_max, |c| c.0);
+            let node = &self.nodes[i];
+            if node
+                .bounds()
+                .intersect(ray, &inv_dir, t_min, t_far)
+                .is_none()
+            {
+                continue;
+            }
+            match *node {
+                Node::Leaf { first, count, .. } => {
+                    for &k in &self.order[first..first + count] {
+                        if let Some((t, b1, b2)) = intersect_triangle(ray, self.triangles[k].v()) {
+                            if t > t_min && t < closest.map_or(t_max, |c| c.0) {
+                                closest = Some((t, k, b1, b2));
+                            }
+                        }
+                    }
+                }
+                // visit the near child first so the far one is culled more often
+                Node::Interior { right, axis, .. } => {
+                    if negative[axis] {
+                        stack.push(i + 1);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(i + 1);
+                    }
+                }
+            }
+        }
+        closest.map(|(t, k, b1, b2)| Hit::new(ray, t, k, &self.triangles[k], b1, b2))
+    }
+}
+
+// Builds the subtree over `order`, whose entries start at `first` in the
+// complete order, and returns its node index
+fn build(
+    bounds: &[Aabb],
+    order: &mut [usize],
+    first: usize,
+    split: SplitMethod,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let node_bounds = order
+        .iter()
+        .fold(Aabb::empty(), |acc, &k| acc.union(&bounds[k]));
+    let index = nodes.len();
+    let leaf = Node::Leaf {
+        bounds: node_bounds,
+        first,
+        count: order.len(),
+    };
+    if order.len() <= MAX_LEAF_SIZE {
+        nodes.push(leaf);
+        return index;
+    }
+
+    let mut centroid_bounds = Aabb::empty();
+    for &k in order.iter() {
+        centroid_bounds.grow(&bounds[k].centroid());
+    }
+    let axis = centroid_bounds.longest_axis();
+    let (lo, hi) = (centroid_bounds.min[axis], centroid_bounds.max[axis]);
+    if hi - lo <= f32::EPSILON {
+        // all centroids coincide, no split separates them
+        nodes.push(leaf);
+        return index;
+    }
+
+    let mid = match split {
+        SplitMethod::Midpoint => partition(order, |k| bounds[k].centroid()[axis] < (lo + hi) * 0.5),
+        SplitMethod::Sah => {
+            let bucket = |k: usize| {
+                let b =
+                    ((bounds[k].centroid()[axis] - lo) / (hi - lo) * SAH_BUCKETS as f32) as usize;
+                b.min(SAH_BUCKETS - 1)
+            };
+            let mut counts = [0usize; SAH_BUCKETS];
+            let mut boxes = [Aabb::empty(); SAH_BUCKETS];
+            for &k in order.iter() {
+                let b = bucket(k);
+                counts[b] += 1;
+                boxes[b] = boxes[b].union(&bounds[k]);
+            }
+
+            // cost of splitting after bucket i, relative to the parent area
+            let mut best = (f32::INFINITY, 0);
+            for i in 0..SAH_BUCKETS - 1 {
+                let (mut left, mut right) = (Aabb::empty(), Aabb::empty());
+                let (mut n_left, mut n_right) = (0, 0);
+                for j in 0..=i {
+                    left = left.union(&boxes[j]);
+                    n_left += counts[j];
+                }
+                for j in i + 1..SAH_BUCKETS {
+                    right = right.union(&boxes[j]);
+                    n_right += counts[j];
+                }
+                if n_left == 0 || n_right == 0 {
+                    continue;
+                }
+                let cost = 0.125
+                    + (n_left as f32 * left.surface_area() + n_right as f32 * right.surface_area())
+                        / node_bounds.surface_area().max(f32::EPSILON);
+                if cost < best.0 {
+                    best = (cost, i);
+                }
+            }
+            // a leaf is cheaper, unless it would grow too large
+            if best.0 >= order.len() as f32 && order.len() <= 4 * MAX_LEAF_SIZE {
+                nodes.push(leaf);
+                return index;
+            }
+            partition(order, |k| bucket(k) <= best.1)
+        }
+    };
+    // degenerate partitions fall back to an even split
+    let mid = if mid == 0 || mid == order.len() {
+        order.sort_by(|&a, &b| bounds[a].centroid()[axis].total_cmp(&bounds[b].centroid()[axis]));
+        order.len() / 2
+    } else {
+        mid
+    };
+
+    nodes.push(Node::Interior {
+        bounds: node_bounds,
+        right: 0,
+        axis,
+    });
+    let (left_order, right_order) = order.split_at_mut(mid);
+    build(bounds, left_order, first, split, nodes);
+    let right = build(bounds, right_order, first + mid, split, nodes);
+    if let Node::Interior { right: r, .. } = &mut nodes[index] {
+        *r = right;
+    }
+    index
+}
+
+// Moves the entries matching `pred` to the front, returns their count
+fn partition(order: &mut [usize], pred: impl Fn(usize) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..order.len() {
+        if pred(order[i]) {
+            order.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+    use crate::ray::intersect_all;
+
+    // Overlapping triangles scattered through a box, big enough to split
+    // into many nodes
+    fn triangle_soup(rng: &mut Rng, count: usize) -> Vec<Triangle> {
+        (0..count)
+            .map(|_| {
+                let center =
+                    Vector3::new(rng.range(-5., 5.), rng.range(-5., 5.), rng.range(-5., 5.));
+                let mut t = Triangle::default();
+                for j in 0..3 {
+                    let offset =
+                        Vector3::new(rng.range(-1., 1.), rng.range(-1., 1.), rng.range(-1., 1.));
+                    t.set_vertex(j, center + offset).unwrap();
+                }
+                t
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closest_hit_matches_brute_force() {
+        let mut rng = Rng::new(7);
+        let triangles = triangle_soup(&mut rng, 300);
+        let midpoint = Bvh::new(triangles.clone(), SplitMethod::Midpoint);
+        let sah = Bvh::new(triangles.clone(), SplitMethod::Sah);
+        assert!(midpoint.node_count() > 1 && sah.node_count() > 1);
+
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let origin = Vector3::new(rng.range(-8., 8.), rng.range(-8., 8.), rng.range(-8., 8.));
+            let target = Vector3::new(rng.range(-5., 5.), rng.range(-5., 5.), rng.range(-5., 5.));
+            let ray = Ray::new(origin, target - origin);
+
+            let expected = intersect_all(&ray, &triangles, 0., f32::INFINITY);
+            for bvh in [&midpoint, &sah] {
+                let hit = bvh.intersect(&ray);
+                match (&expected, &hit) {
+                    (None, None) => {}
+                    (Some(expected), Some(hit)) => {
+                        assert_eq!(hit.triangle, expected.triangle);
+                        assert!((hit.t - expected.t).abs() < 1e-5);
+                        assert!((hit.barycentric - expected.barycentric).norm() < 1e-5);
+                        assert!((hit.barycentric.sum() - 1.).abs() < 1e-5);
+                    }
+                    _ => panic!("bvh hit {hit:?}, brute force hit {expected:?}"),
+                }
+            }
+            hits += expected.is_some() as usize;
+        }
+        // most rays aim into the soup
+        assert!(hits > 500);
+    }
+}
@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+
+use crate::transform::{look_at, oblique, orthographic, perspective, translation};
+use nalgebra::{Matrix4, Rotation3, Unit, Vector3};
+
+// Closest the orbit and look directions get to `up`, as a cosine
+const MAX_PITCH_COS: f32 = 0.995;
+// Orbit distance never shrinks below this
+const MIN_DISTANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    // Mouse rotates around and zooms towards the target
+    Orbit,
+    // Mouse turns the view, keys walk, the target moves with the eye
+    FirstPerson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // Parallel projection showing the target plane at the size perspective would
+    Orthographic,
+    // Orthographic with receding lines at `angle` degrees, shortened by
+    // `depth_scale`, pivoting around the target plane
+    Oblique { angle: f32, depth_scale: f32 },
+}
+
+impl Projection {
+    pub fn next(self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic,
+            // cabinet projection
+            Projection::Orthographic => Projection::Oblique {
+                angle: 45.,
+                depth_scale: 0.5,
+            },
+            Projection::Oblique { .. } => Projection::Perspective,
+        }
+    }
+}
+
+// Camera looking from `eye` at `target`, providing the matrices for
+// `Rasterizer::set_view` and `set_projection`
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub eye: Vector3<f32>,
+    pub target: Vector3<f32>,
+    pub up: Vector3<f32>,
+    // vertical field of view in degrees
+    pub fov: f32,
+    pub aspect_ratio: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+    pub projection: Projection,
+    pub mode: CameraMode,
+    // world units per `walk` step
+    pub move_speed: f32,
+    // radians per pixel of mouse movement
+    pub rotate_speed: f32,
+    last_mouse: Option<(i32, i32)>,
+}
+
+impl Camera {
+    pub fn new(eye: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) -> Self {
+        Self {
+            eye,
+            target,
+            up: up.normalize(),
+            fov: 45.,
+            aspect_ratio: 1.,
+            z_near: 0.1,
+            z_far: 50.,
+            projection: Projection::Perspective,
+            mode: CameraMode::Orbit,
+            move_speed: 0.25,
+            rotate_speed: 0.01,
+            last_mouse: None,
+        }
+    }
+
+    pub fn look_at(&mut self, eye: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) {
+        self.eye = eye;
+        self.target = target;
+        self.up = up.normalize();
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        look_at(&self.eye, &self.target, &self.up)
+    }
+
+    pub fn projection(&self) -> Matrix4<f32> {
+        let t = self.distance() * (self.fov.to_radians() / 2.).tan();
+        let r = t * self.aspect_ratio;
+        match self.projection {
+            Projection::Perspective => {
+                perspective(self.fov, self.aspect_ratio, self.z_near, self.z_far)
+            }
+            Projection::Orthographic => orthographic(-r, r, -t, t, self.z_near, self.z_far),
+            Projection::Oblique { angle, depth_scale } => {
+                // shear around the target plane instead of the eye so the
+                // target stays centered
+                let d = self.distance();
+                let depth = (self.z_near - d, self.z_far - d);
+                oblique((-r, r), (-t, t), depth, angle, depth_scale)
+                    * translation(&Vector3::new(0., 0., d))
+            }
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        (self.target - self.eye).normalize()
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.forward()
+            .cross(&self.up)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::x)
+    }
+
+    pub fn distance(&self) -> f32 {
+        (self.target - self.eye).norm()
+    }
+
+    // Moves the eye around the target, yaw about `up` and pitch about `right`
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let offset = rotate(
+            &(self.eye - self.target),
+            &self.up,
+            &self.right(),
+            yaw,
+            pitch,
+        );
+        self.eye = self.target + offset;
+    }
+
+    // Turns the view direction in place
+    pub fn turn(&mut self, yaw: f32, pitch: f32) {
+        let direction = rotate(
+            &(self.target - self.eye),
+            &self.up,
+            &self.right(),
+            yaw,
+            pitch,
+        );
+        self.target = self.eye + direction;
+    }
+
+    // Shifts eye and target in the view plane, by pixels on a `height`
+    // pixel tall screen at the target distance
+    pub fn pan(&mut self, dx: f32, dy: f32, height: f32) {
+        let world_per_pixel =
+            2. * self.distance() * (self.fov.to_radians() / 2.).tan() / height.max(1.);
+        let right = self.right();
+        let up = right.cross(&self.forward());
+        let shift = (-right * dx + up * dy) * world_per_pixel;
+        self.eye += shift;
+        self.target += shift;
+    }
+
+    // Positive steps move towards the target, each one by 10%
+    pub fn zoom(&mut self, steps: f32) {
+        let distance = (self.distance() * 0.9f32.powf(steps)).max(MIN_DISTANCE);
+        self.eye = self.target - self.forward() * distance;
+    }
+
+    // First person movement in steps of `move_speed`, forward and right stay
+    // level with the ground
+    pub fn walk(&mut self, forward: f32, right: f32, up: f32) {
+        let ahead = self.up.cross(&self.right());
+        let shift = (ahead * forward + self.right() * right + self.up * up) * self.move_speed;
+        self.eye += shift;
+        self.target += shift;
+    }
+
+    // Mouse movement to window coordinates `x`, `y` with the buttons held.
+    // The left button orbits or turns, the right one pans. Returns whether
+    // the camera moved.
+    pub fn mouse_move(&mut self, x: i32, y: i32, left: bool, right: bool, height: f32) -> bool {
+        let last = self.last_mouse.replace((x, y));
+        let Some((last_x, last_y)) = last else {
+            return false;
+        };
+        let (dx, dy) = ((x - last_x) as f32, (y - last_y) as f32);
+        if dx == 0. && dy == 0. {
+            return false;
+        }
+        if left {
+            let (yaw, pitch) = (-dx * self.rotate_speed, -dy * self.rotate_speed);
+            match self.mode {
+                CameraMode::Orbit => self.orbit(yaw, pitch),
+                CameraMode::FirstPerson => self.turn(yaw, pitch),
+            }
+        } else if right {
+            self.pan(dx, dy, height);
+        }
+        left || right
+    }
+
+    // Mouse wheel, positive away from the user
+    pub fn wheel(&mut self, delta: f32) {
+        match self.mode {
+            CameraMode::Orbit => self.zoom(delta),
+            CameraMode::FirstPerson => self.walk(delta, 0., 0.),
+        }
+    }
+}
+
+// Yaw about `up`, then pitch about `right` unless that would tip `v` over
+fn rotate(
+    v: &Vector3<f32>,
+    up: &Vector3<f32>,
+    right: &Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+) -> Vector3<f32> {
+    let yaw = Rotation3::from_axis_angle(&Unit::new_normalize(*up), yaw);
+    let yawed = yaw * v;
+    let pitched = Rotation3::from_axis_angle(&Unit::new_normalize(yaw * right), pitch) * yawed;
+    if pitched.normalize().dot(up).abs() < MAX_PITCH_COS {
+        pitched
+    } else {
+        yawed
+    }
+}
@@ -0,0 +1,205 @@
+#![allow(dead_code)]
+
+use nalgebra::Vector2;
+
+// Bezier curve of degree `control_points.len() - 1`, parameter in [0, 1]
+#[derive(Debug, Clone, Default)]
+pub struct Bezier {
+    pub control_points: Vec<Vector2<f32>>,
+}
+
+impl Bezier {
+    pub fn new(control_points: Vec<Vector2<f32>>) -> Self {
+        Self { control_points }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.control_points.len().saturating_sub(1)
+    }
+
+    pub fn evaluate(&self, t: f32) -> Option<Vector2<f32>> {
+        de_casteljau_levels(&self.control_points, t)
+            .last()
+            .map(|level| level[0])
+    }
+
+    // Polyline through `segments + 1` evenly spaced parameters
+    pub fn sample(&self, segments: usize) -> Vec<Vector2<f32>> {
+        let segments = segments.max(1);
+        (0..=segments)
+            .filter_map(|i| self.evaluate(i as f32 / segments as f32))
+            .collect()
+    }
+}
+
+// Every level of de Casteljau's algorithm: the control points first, then
+// each round of linear interpolation down to the single point on the curve
+pub fn de_casteljau_levels(points: &[Vector2<f32>], t: f32) -> Vec<Vec<Vector2<f32>>> {
+    if points.is_empty() {
+        return Vec::new();
+    }
+    let mut levels = vec![points.to_vec()];
+    while levels.last().map_or(0, |l| l.len()) > 1 {
+        let last = levels.last().unwrap();
+        let next = last.windows(2).map(|p| p[0].lerp(&p[1], t)).collect();
+        levels.push(next);
+    }
+    levels
+}
+
+// B-spline of the given degree over a non-decreasing knot vector with
+// `control_points.len() + degree + 1` entries
+#[derive(Debug, Clone)]
+pub struct BSpline {
+    pub control_points: Vec<Vector2<f32>>,
+    degree: usize,
+    knots: Vec<f32>,
+}
+
+impl BSpline {
+    pub fn new(
+        control_points: Vec<Vector2<f32>>,
+        degree: usize,
+        knots: Vec<f32>,
+    ) -> Result<Self, String> {
+        if control_points.len() <= degree {
+            return Err("B-spline needs more control points than its degree".to_string());
+        }
+        if knots.len() != control_points.len() + degree + 1 {
+            return Err("Invalid knot count".to_string());
+        }
+        if knots.windows(2).any(|k| k[1] < k[0]) {
+            return Err("Knots must be non-decreasing".to_string());
+        }
+        Ok(Self {
+            control_points,
+            degree,
+            knots,
+        })
+    }
+
+    // Evenly spaced knots, the curve does not touch its end points
+    pub fn uniform(control_points: Vec<Vector2<f32>>, degree: usize) -> Result<Self, String> {
+        let knots = (0..control_points.len() + degree + 1)
+            .map(|i| i as f32)
+            .collect();
+        Self::new(control_points, degree, knots)
+    }
+
+    // End knots repeated degree + 1 times so the curve starts and ends at the
+    // first and last control point, inner knots evenly spaced
+    pub fn clamped(control_points: Vec<Vector2<f32>>, degree: usize) -> Result<Self, String> {
+        let n = control_points.len();
+        if n <= degree {
+            return Err("B-spline needs more control points than its degree".to_string());
+        }
+        let spans = (n - degree) as f32;
+        let knots = (0..n + degree + 1)
+            .map(|i| (i as f32 - degree as f32).clamp(0., spans) / spans)
+            .collect();
+        Self::new(control_points, degree, knots)
+    }
+
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+
+    pub fn knots(&self) -> &[f32] {
+        &self.knots
+    }
+
+    // Parameter range where the basis functions sum to one
+    pub fn domain(&self) -> (f32, f32) {
+        (
+            self.knots[self.degree],
+            self.knots[self.control_points.len()],
+        )
+    }
+
+    // de Boor's algorithm, the B-spline counterpart of de Casteljau
+    pub fn evaluate(&self, t: f32) -> Vector2<f32> {
+        let p = self.degree;
+        let (lo, hi) = self.domain();
+        let t = t.clamp(lo, hi);
+        // knot span with knots[k] <= t < knots[k + 1], the last one is closed
+        let k = (p..self.control_points.len())
+            .rev()
+            .find(|&k| self.knots[k] <= t && self.knots[k] < self.knots[k + 1])
+            .unwrap_or(p);
+
+        let mut d: Vec<Vector2<f32>> = (0..=p).map(|j| self.control_points[j + k - p]).collect();
+        for r in 1..=p {
+            for j in (r..=p).rev() {
+                let i = j + k - p;
+                let denom = self.knots[i + p + 1 - r] - self.knots[i];
+                let alpha = if denom > 0. {
+                    (t - self.knots[i]) / denom
+                } else {
+                    0.
+                };
+                d[j] = d[j - 1].lerp(&d[j], alpha);
+            }
+        }
+        d[p]
+    }
+
+    // Polyline through `segments + 1` evenly spaced parameters of the domain
+    pub fn sample(&self, segments: usize) -> Vec<Vector2<f32>> {
+        let segments = segments.max(1);
+        let (lo, hi) = self.domain();
+        (0..=segments)
+            .map(|i| self.evaluate(lo + (hi - lo) * i as f32 / segments as f32))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control_points() -> Vec<Vector2<f32>> {
+        vec![
+            Vector2::new(0., 0.),
+            Vector2::new(1., 3.),
+            Vector2::new(4., 3.),
+            Vector2::new(5., 0.),
+            Vector2::new(7., 2.),
+        ]
+    }
+
+    #[test]
+    fn de_casteljau_hits_the_endpoints() {
+        let points = control_points();
+        let bezier = Bezier::new(points.clone());
+        assert_eq!(bezier.evaluate(0.), points.first().copied());
+        assert_eq!(bezier.evaluate(1.), points.last().copied());
+
+        // one level per degree plus the control points, down to one point
+        let levels = de_casteljau_levels(&points, 0.5);
+        assert_eq!(levels.len(), points.len());
+        assert_eq!(levels.last().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn de_casteljau_matches_the_bernstein_form() {
+        let points = control_points();
+        let bezier = Bezier::new(points.clone());
+        let t: f32 = 0.3;
+        // binomial coefficients of degree 4
+        let expected = [1., 4., 6., 4., 1.]
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| points[i] * c * t.powi(i as i32) * (1. - t).powi(4 - i as i32))
+            .sum::<Vector2<f32>>();
+        assert!((bezier.evaluate(t).unwrap() - expected).norm() < 1e-5);
+    }
+
+    #[test]
+    fn clamped_bspline_hits_the_endpoints() {
+        let points = control_points();
+        let spline = BSpline::clamped(points.clone(), 3).unwrap();
+        let (lo, hi) = spline.domain();
+        assert!((spline.evaluate(lo) - points[0]).norm() < 1e-5);
+        assert!((spline.evaluate(hi) - points[4]).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,165 @@
+#![allow(dead_code)]
+
+use crate::curve::{de_casteljau_levels, BSpline, Bezier};
+use crate::rst::{Buffers, Rasterizer};
+use nalgebra::{Vector2, Vector3};
+
+// Control points closer than this many pixels to the cursor are picked
+const PICK_RADIUS: f32 = 8.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveKind {
+    Bezier,
+    UniformBSpline,
+    ClampedBSpline,
+}
+
+impl CurveKind {
+    pub fn next(self) -> Self {
+        match self {
+            CurveKind::Bezier => CurveKind::UniformBSpline,
+            CurveKind::UniformBSpline => CurveKind::ClampedBSpline,
+            CurveKind::ClampedBSpline => CurveKind::Bezier,
+        }
+    }
+}
+
+// State of the interactive curve editor. Points are in rasterizer screen
+// space, mouse positions are converted with `to_screen`.
+pub struct CurveEditor {
+    pub points: Vec<Vector2<f32>>,
+    pub kind: CurveKind,
+    // Degree of the B-splines, lowered while there are too few points
+    pub degree: usize,
+    pub show_polygon: bool,
+    pub show_levels: bool,
+    // Parameter at which the de Casteljau levels are shown
+    pub t: f32,
+    // Draw the curve with `Rasterizer::draw_curve` instead of `draw_line`
+    pub antialiasing: bool,
+    dragging: Option<usize>,
+}
+
+impl Default for CurveEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CurveEditor {
+    pub fn new() -> Self {
+        Self {
+            points: Vec::new(),
+            kind: CurveKind::Bezier,
+            degree: 3,
+            show_polygon: true,
+            show_levels: false,
+            t: 0.5,
+            antialiasing: true,
+            dragging: None,
+        }
+    }
+
+    // Mouse coordinates have y pointing down, the rasterizer has it up
+    pub fn to_screen(x: i32, y: i32, height: usize) -> Vector2<f32> {
+        Vector2::new(x as f32 + 0.5, height as f32 - y as f32 - 0.5)
+    }
+
+    fn pick(&self, p: &Vector2<f32>) -> Option<usize> {
+        self.points
+            .iter()
+            .enumerate()
+            .map(|(i, q)| (i, (q - p).norm()))
+            .filter(|&(_, d)| d < PICK_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Picks the point under the cursor for dragging or adds a new one
+    pub fn press(&mut self, p: Vector2<f32>) {
+        self.dragging = match self.pick(&p) {
+            Some(i) => Some(i),
+            None => {
+                self.points.push(p);
+                Some(self.points.len() - 1)
+            }
+        };
+    }
+
+    pub fn drag(&mut self, p: Vector2<f32>) {
+        if let Some(i) = self.dragging {
+            self.points[i] = p;
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.dragging = None;
+    }
+
+    pub fn delete(&mut self, p: Vector2<f32>) {
+        if let Some(i) = self.pick(&p) {
+            self.points.remove(i);
+            self.dragging = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.dragging = None;
+    }
+
+    // Polyline of the current curve, empty while there are too few points
+    pub fn curve(&self, segments: usize) -> Vec<Vector2<f32>> {
+        if self.points.len() < 2 {
+            return Vec::new();
+        }
+        let degree = self.degree.clamp(1, self.points.len() - 1);
+        let spline = match self.kind {
+            CurveKind::Bezier => return Bezier::new(self.points.clone()).sample(segments),
+            CurveKind::UniformBSpline => BSpline::uniform(self.points.clone(), degree),
+            CurveKind::ClampedBSpline => BSpline::clamped(self.points.clone(), degree),
+        };
+        spline.map(|s| s.sample(segments)).unwrap_or_default()
+    }
+
+    pub fn draw(&self, r: &mut Rasterizer) {
+        r.clear(Buffers::Color | Buffers::Depth);
+        let to_3d = |p: &Vector2<f32>| Vector3::new(p.x, p.y, 1.);
+        let polyline = |r: &mut Rasterizer, points: &[Vector2<f32>], color: &Vector3<f32>| {
+            for segment in points.windows(2) {
+                r.draw_line(&to_3d(&segment[0]), &to_3d(&segment[1]), color);
+            }
+        };
+
+        // lines only go into the samples, the first one drawn at a pixel wins
+        let curve = self.curve(200);
+        if !self.antialiasing {
+            polyline(r, &curve, &Vector3::new(0., 255., 0.));
+        }
+        if self.show_polygon {
+            polyline(r, &self.points, &Vector3::new(128., 128., 128.));
+        }
+        if self.show_levels && self.kind == CurveKind::Bezier {
+            let levels = de_casteljau_levels(&self.points, self.t);
+            let count = levels.len().max(2) - 1;
+            for (i, level) in levels.iter().enumerate().skip(1) {
+                let k = i as f32 / count as f32;
+                let color = Vector3::new(255. * k, 160. * (1. - k), 255. * (1. - k));
+                polyline(r, level, &color);
+            }
+        }
+        r.resolve_sample();
+
+        if self.antialiasing {
+            r.draw_curve(&curve, &Vector3::new(0., 255., 0.), 2.);
+        }
+        for p in &self.points {
+            r.draw_curve(&[*p], &Vector3::new(255., 255., 255.), 5.);
+        }
+        if self.show_levels && self.kind == CurveKind::Bezier {
+            if let Some(point) = Bezier::new(self.points.clone()).evaluate(self.t) {
+                r.draw_curve(&[point], &Vector3::new(255., 0., 0.), 7.);
+            }
+        }
+    }
+}
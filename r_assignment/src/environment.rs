@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+
+use crate::texture::HdrTexture;
+use nalgebra::{Vector2, Vector3};
+use std::f32::consts::PI;
+
+// Distant lighting from an equirectangular HDR image. Diffuse irradiance is
+// kept as 9 spherical harmonics coefficients and specular reflections are
+// prefiltered with the GGX lobe for evenly spaced roughness levels.
+pub struct EnvironmentMap {
+    radiance: HdrTexture,
+    irradiance_sh: [Vector3<f32>; 9],
+    // entry k - 1 is filtered for roughness k / (levels - 1), level 0 is
+    // `radiance` itself
+    prefiltered: Vec<HdrTexture>,
+}
+
+impl EnvironmentMap {
+    pub fn load(name: &str, levels: usize) -> Result<Self, String> {
+        Ok(Self::new(HdrTexture::new(name)?, levels))
+    }
+
+    pub fn new(radiance: HdrTexture, levels: usize) -> Self {
+        let small = radiance.downsample(radiance.width.min(128), radiance.height.min(64));
+        let irradiance_sh = project_sh(&small);
+
+        let levels = levels.max(1);
+        let prefiltered = (1..levels)
+            .map(|k| {
+                let roughness = k as f32 / (levels - 1) as f32;
+                let width = 256i32.checked_shr(k as u32).unwrap_or(0).max(16);
+                let source = radiance
+                    .downsample((2 * width).min(radiance.width), width.min(radiance.height));
+                prefilter(&source, roughness, width, width / 2, 128)
+            })
+            .collect();
+
+        Self {
+            radiance,
+            irradiance_sh,
+            prefiltered,
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.prefiltered.len() + 1
+    }
+
+    // Radiance arriving from direction `dir` (world space)
+    pub fn radiance(&self, dir: &Vector3<f32>) -> Vector3<f32> {
+        let uv = direction_to_uv(dir);
+        self.radiance.get_color(uv.x, uv.y)
+    }
+
+    // Cosine weighted integral of the incoming radiance around `normal`,
+    // a Lambertian surface reflects albedo / pi times this
+    pub fn irradiance(&self, normal: &Vector3<f32>) -> Vector3<f32> {
+        let basis = sh_basis(&normal.normalize());
+        let band = [
+            PI,
+            2. * PI / 3.,
+            2. * PI / 3.,
+            2. * PI / 3.,
+            PI / 4.,
+            PI / 4.,
+            PI / 4.,
+            PI / 4.,
+            PI / 4.,
+        ];
+        let mut e = Vector3::zeros();
+        for i in 0..9 {
+            e += self.irradiance_sh[i] * band[i] * basis[i];
+        }
+        e.map(|c| c.max(0.))
+    }
+
+    // Prefiltered radiance around the reflected direction `dir`
+    pub fn specular(&self, dir: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+        let uv = direction_to_uv(dir);
+        let level = roughness.clamp(0., 1.) * self.prefiltered.len() as f32;
+        let lo = level.floor() as usize;
+        let hi = level.ceil() as usize;
+        let fetch = |k: usize| match k {
+            0 => self.radiance.get_color(uv.x, uv.y),
+            k => self.prefiltered[k - 1].get_color(uv.x, uv.y),
+        };
+        if lo == hi {
+            fetch(lo)
+        } else {
+            fetch(lo).lerp(&fetch(hi), level - lo as f32)
+        }
+    }
+}
+
+// -z maps to the center of the image and +y to the top row
+pub fn direction_to_uv(dir: &Vector3<f32>) -> Vector2<f32> {
+    let d = dir.normalize();
+    Vector2::new(
+        0.5 + d.x.atan2(-d.z) / (2. * PI),
+        0.5 + d.y.clamp(-1., 1.).asin() / PI,
+    )
+}
+
+pub fn uv_to_direction(u: f32, v: f32) -> Vector3<f32> {
+    let phi = (u - 0.5) * 2. * PI;
+    let latitude = (v - 0.5) * PI;
+    let r = latitude.cos();
+    Vector3::new(r * phi.sin(), latitude.sin(), -r * phi.cos())
+}
+
+fn sh_basis(d: &Vector3<f32>) -> [f32; 9] {
+    let (x, y, z) = (d.x, d.y, d.z);
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3. * z * z - 1.),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+fn project_sh(texture: &HdrTexture) -> [Vector3<f32>; 9] {
+    let (w, h) = (texture.width, texture.height);
+    let mut coeffs = [Vector3::zeros(); 9];
+    for y in 0..h {
+        let v = 1. - (y as f32 + 0.5) / h as f32;
+        let latitude = (v - 0.5) * PI;
+        let solid_angle = (2. * PI / w as f32) * (PI / h as f32) * latitude.cos();
+        for x in 0..w {
+            let u = (x as f32 + 0.5) / w as f32;
+            let basis = sh_basis(&uv_to_direction(u, v));
+            let radiance = texture.texel(x, y) * solid_angle;
+            for i in 0..9 {
+                coeffs[i] += radiance * basis[i];
+            }
+        }
+    }
+    coeffs
+}
+
+// Low discrepancy point set in [0, 1)^2
+fn hammersley(i: u32, n: u32) -> Vector2<f32> {
+    Vector2::new(
+        i as f32 / n as f32,
+        i.reverse_bits() as f32 * 2.328_306_4e-10,
+    )
+}
+
+// Half vector distributed with the GGX lobe around `n`
+pub fn importance_sample_ggx(xi: &Vector2<f32>, n: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let a = roughness * roughness;
+    let phi = 2. * PI * xi.x;
+    let cos_theta = ((1. - xi.y) / (1. + (a * a - 1.) * xi.y)).sqrt();
+    let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+
+    let up = if n.z.abs() < 0.999 {
+        Vector3::z()
+    } else {
+        Vector3::x()
+    };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(&tangent);
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + n * cos_theta)
+        .normalize()
+}
+
+// Split-sum prefiltering with n = v = r
+fn prefilter(
+    source: &HdrTexture,
+    roughness: f32,
+    width: i32,
+    height: i32,
+    samples: u32,
+) -> HdrTexture {
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f32 + 0.5) / width as f32;
+            let v = 1. - (y as f32 + 0.5) / height as f32;
+            let n = uv_to_direction(u, v);
+
+            let mut sum = Vector3::zeros();
+            let mut weight = 0.;
+            for i in 0..samples {
+                let h = importance_sample_ggx(&hammersley(i, samples), &n, roughness);
+                let l = 2. * n.dot(&h) * h - n;
+                let n_dot_l = n.dot(&l);
+                if n_dot_l > 0. {
+                    let uv = direction_to_uv(&l);
+                    sum += source.get_color(uv.x, uv.y) * n_dot_l;
+                    weight += n_dot_l;
+                }
+            }
+            data.push(if weight > 0. { sum / weight } else { sum });
+        }
+    }
+    HdrTexture::from_pixels(width, height, data)
+}
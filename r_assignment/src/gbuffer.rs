@@ -0,0 +1,57 @@
+#![allow(dead_code)]
+
+use nalgebra::Vector3;
+
+// Per-pixel surface attributes written by the geometry pass, positions and
+// normals are in view space. Coverage is tracked by the rasterizer depth buffer.
+pub struct GBuffer {
+    width: usize,
+    height: usize,
+
+    pub position: Vec<Vector3<f32>>,
+    pub normal: Vec<Vector3<f32>>,
+    pub albedo: Vec<Vector3<f32>>,
+    pub material_id: Vec<usize>,
+}
+
+impl GBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            position: vec![Vector3::default(); width * height],
+            normal: vec![Vector3::default(); width * height],
+            albedo: vec![Vector3::default(); width * height],
+            material_id: vec![0; width * height],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.position.fill(Vector3::default());
+        self.normal.fill(Vector3::default());
+        self.albedo.fill(Vector3::default());
+        self.material_id.fill(0);
+    }
+
+    pub fn write(
+        &mut self,
+        ind: usize,
+        position: Vector3<f32>,
+        normal: Vector3<f32>,
+        albedo: Vector3<f32>,
+        material_id: usize,
+    ) {
+        self.position[ind] = position;
+        self.normal[ind] = normal;
+        self.albedo[ind] = albedo;
+        self.material_id[ind] = material_id;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
@@ -0,0 +1,392 @@
+#![allow(dead_code)]
+
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HalfEdge {
+    // vertex the half-edge starts at
+    pub origin: usize,
+    pub next: usize,
+    // None on the boundary
+    pub twin: Option<usize>,
+    pub face: usize,
+    // undirected edge shared with the twin
+    pub edge: usize,
+}
+
+// Manifold polygon mesh, faces wound counter-clockwise. Colors are either
+// empty or one per vertex and are carried through subdivision like positions.
+#[derive(Debug, Clone, Default)]
+pub struct HalfEdgeMesh {
+    pub positions: Vec<Vector3<f32>>,
+    pub colors: Vec<Vector3<f32>>,
+    half_edges: Vec<HalfEdge>,
+    // first half-edge of every face
+    faces: Vec<usize>,
+    edge_count: usize,
+}
+
+impl HalfEdgeMesh {
+    // From the vectors accepted by `Rasterizer::load_positions` and `load_indices`
+    pub fn from_triangles(
+        positions: Vec<Vector3<f32>>,
+        indices: &[Vector3<i32>],
+    ) -> Result<Self, String> {
+        let faces: Vec<Vec<usize>> = indices
+            .iter()
+            .map(|f| f.iter().map(|&i| i as usize).collect())
+            .collect();
+        Self::from_polygons(positions, &faces)
+    }
+
+    pub fn from_polygons(
+        positions: Vec<Vector3<f32>>,
+        faces: &[Vec<usize>],
+    ) -> Result<Self, String> {
+        let mut mesh = Self {
+            positions,
+            ..Default::default()
+        };
+        let mut directed: HashMap<(usize, usize), usize> = HashMap::new();
+        for (face, vertices) in faces.iter().enumerate() {
+            if vertices.len() < 3 {
+                return Err("Faces need at least 3 vertices".to_string());
+            }
+            if vertices.iter().any(|&v| v >= mesh.positions.len()) {
+                return Err("Invalid ind".to_string());
+            }
+            let first = mesh.half_edges.len();
+            mesh.faces.push(first);
+            for (k, &origin) in vertices.iter().enumerate() {
+                let target = vertices[(k + 1) % vertices.len()];
+                let he = first + k;
+                if directed.insert((origin, target), he).is_some() {
+                    return Err("Non-manifold or inconsistently wound edge".to_string());
+                }
+                mesh.half_edges.push(HalfEdge {
+                    origin,
+                    next: first + (k + 1) % vertices.len(),
+                    twin: None,
+                    face,
+                    edge: 0,
+                });
+            }
+        }
+
+        for he in 0..mesh.half_edges.len() {
+            let (a, b) = (mesh.origin(he), mesh.target(he));
+            let twin = directed.get(&(b, a)).copied();
+            mesh.half_edges[he].twin = twin;
+            match twin {
+                Some(t) if t < he => mesh.half_edges[he].edge = mesh.half_edges[t].edge,
+                _ => {
+                    mesh.half_edges[he].edge = mesh.edge_count;
+                    mesh.edge_count += 1;
+                }
+            }
+        }
+        Ok(mesh)
+    }
+
+    pub fn set_colors(&mut self, colors: Vec<Vector3<f32>>) -> Result<(), String> {
+        if !colors.is_empty() && colors.len() != self.positions.len() {
+            return Err("Colors need one entry per vertex".to_string());
+        }
+        self.colors = colors;
+        Ok(())
+    }
+
+    pub fn half_edges(&self) -> &[HalfEdge] {
+        &self.half_edges
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+
+    pub fn origin(&self, he: usize) -> usize {
+        self.half_edges[he].origin
+    }
+
+    pub fn target(&self, he: usize) -> usize {
+        self.half_edges[self.half_edges[he].next].origin
+    }
+
+    // Half-edges around a face, starting at its first one
+    pub fn face_half_edges(&self, face: usize) -> Vec<usize> {
+        let first = self.faces[face];
+        let mut result = vec![first];
+        let mut he = self.half_edges[first].next;
+        while he != first {
+            result.push(he);
+            he = self.half_edges[he].next;
+        }
+        result
+    }
+
+    pub fn face_vertices(&self, face: usize) -> Vec<usize> {
+        self.face_half_edges(face)
+            .into_iter()
+            .map(|he| self.origin(he))
+            .collect()
+    }
+
+    pub fn is_triangle_mesh(&self) -> bool {
+        (0..self.faces.len()).all(|f| self.face_half_edges(f).len() == 3)
+    }
+
+    // Fan triangulation for `Rasterizer::load_indices`
+    pub fn triangles(&self) -> Vec<Vector3<i32>> {
+        let mut indices = Vec::new();
+        for face in 0..self.faces.len() {
+            let v = self.face_vertices(face);
+            for k in 1..v.len() - 1 {
+                indices.push(Vector3::new(v[0] as i32, v[k] as i32, v[k + 1] as i32));
+            }
+        }
+        indices
+    }
+
+    // Loop subdivision: every triangle is split in four, vertices are
+    // smoothed with Warren's weights and boundaries follow the cubic
+    // B-spline rule
+    pub fn loop_subdivide(&self) -> Result<Self, String> {
+        if !self.is_triangle_mesh() {
+            return Err("Loop subdivision needs a triangle mesh".to_string());
+        }
+        let neighbors = self.vertex_neighbors();
+        let mut stencils: Vec<Vec<(usize, f32)>> = neighbors
+            .iter()
+            .enumerate()
+            .map(|(v, (ring, boundary))| match (ring.len(), boundary.len()) {
+                (_, 2) => vec![(v, 0.75), (boundary[0], 0.125), (boundary[1], 0.125)],
+                (n, 0) if n >= 3 => {
+                    let beta = if n == 3 {
+                        3. / 16.
+                    } else {
+                        3. / (8. * n as f32)
+                    };
+                    let mut s = vec![(v, 1. - n as f32 * beta)];
+                    s.extend(ring.iter().map(|&u| (u, beta)));
+                    s
+                }
+                // corners and non-manifold vertices stay put
+                _ => vec![(v, 1.)],
+            })
+            .collect();
+
+        let mut edge_points = vec![Vec::new(); self.edge_count];
+        for (he, h) in self.half_edges.iter().enumerate() {
+            if h.twin.is_some_and(|t| t < he) {
+                continue;
+            }
+            let (a, b) = (self.origin(he), self.target(he));
+            edge_points[h.edge] = match h.twin {
+                Some(t) => {
+                    let c = self.origin(self.half_edges[h.next].next);
+                    let d = self.origin(self.half_edges[self.half_edges[t].next].next);
+                    vec![(a, 0.375), (b, 0.375), (c, 0.125), (d, 0.125)]
+                }
+                None => vec![(a, 0.5), (b, 0.5)],
+            };
+        }
+
+        let n = self.positions.len();
+        stencils.extend(edge_points);
+        let mut faces = Vec::with_capacity(self.faces.len() * 4);
+        for face in 0..self.faces.len() {
+            let he = self.face_half_edges(face);
+            let v = he.iter().map(|&h| self.origin(h)).collect::<Vec<_>>();
+            let e = he
+                .iter()
+                .map(|&h| n + self.half_edges[h].edge)
+                .collect::<Vec<_>>();
+            // e[k] lies between v[k] and v[k + 1]
+            faces.push(vec![v[0], e[0], e[2]]);
+            faces.push(vec![e[0], v[1], e[1]]);
+            faces.push(vec![e[2], e[1], v[2]]);
+            faces.push(vec![e[0], e[1], e[2]]);
+        }
+        self.refine(&stencils, &faces)
+    }
+
+    // Catmull-Clark subdivision of any polygon mesh, the result is all quads.
+    // Boundaries follow the cubic B-spline rule.
+    pub fn catmull_clark(&self) -> Result<Self, String> {
+        let n = self.positions.len();
+        let face_points: Vec<Vec<(usize, f32)>> = (0..self.faces.len())
+            .map(|f| {
+                let v = self.face_vertices(f);
+                let w = 1. / v.len() as f32;
+                v.into_iter().map(|u| (u, w)).collect()
+            })
+            .collect();
+
+        let mut edge_points = vec![Vec::new(); self.edge_count];
+        for (he, h) in self.half_edges.iter().enumerate() {
+            if h.twin.is_some_and(|t| t < he) {
+                continue;
+            }
+            let (a, b) = (self.origin(he), self.target(he));
+            edge_points[h.edge] = match h.twin {
+                Some(t) => {
+                    let mut s = vec![(a, 0.25), (b, 0.25)];
+                    let faces = [h.face, self.half_edges[t].face];
+                    for f in faces {
+                        s.extend(face_points[f].iter().map(|&(u, w)| (u, w * 0.25)));
+                    }
+                    s
+                }
+                None => vec![(a, 0.5), (b, 0.5)],
+            };
+        }
+
+        let neighbors = self.vertex_neighbors();
+        let mut vertex_faces = vec![Vec::new(); n];
+        for f in 0..self.faces.len() {
+            for v in self.face_vertices(f) {
+                vertex_faces[v].push(f);
+            }
+        }
+        let vertex_points = neighbors.iter().enumerate().map(|(v, (ring, boundary))| {
+            let k = ring.len();
+            match boundary.len() {
+                2 => vec![(v, 0.75), (boundary[0], 0.125), (boundary[1], 0.125)],
+                // (F + 2R + (k - 3) P) / k, R averages the edge midpoints
+                0 if k >= 3 && vertex_faces[v].len() == k => {
+                    let k = k as f32;
+                    let mut s = vec![(v, (k - 3.) / k + 1. / k)];
+                    s.extend(ring.iter().map(|&u| (u, 1. / (k * k))));
+                    for &f in &vertex_faces[v] {
+                        s.extend(face_points[f].iter().map(|&(u, w)| (u, w / (k * k))));
+                    }
+                    s
+                }
+                _ => vec![(v, 1.)],
+            }
+        });
+
+        let mut stencils: Vec<Vec<(usize, f32)>> = vertex_points.collect();
+        let edge_base = n;
+        let face_base = n + self.edge_count;
+        stencils.extend(edge_points);
+        stencils.extend(face_points);
+
+        let mut faces = Vec::new();
+        for face in 0..self.faces.len() {
+            let he = self.face_half_edges(face);
+            let count = he.len();
+            for k in 0..count {
+                let previous = he[(k + count - 1) % count];
+                faces.push(vec![
+                    self.origin(he[k]),
+                    edge_base + self.half_edges[he[k]].edge,
+                    face_base + face,
+                    edge_base + self.half_edges[previous].edge,
+                ]);
+            }
+        }
+        self.refine(&stencils, &faces)
+    }
+
+    // Neighbouring vertices of every vertex, and the ones across boundary edges
+    fn vertex_neighbors(&self) -> Vec<(Vec<usize>, Vec<usize>)> {
+        let mut neighbors = vec![(Vec::new(), Vec::new()); self.positions.len()];
+        for (he, h) in self.half_edges.iter().enumerate() {
+            if h.twin.is_some_and(|t| t < he) {
+                continue;
+            }
+            let (a, b) = (self.origin(he), self.target(he));
+            neighbors[a].0.push(b);
+            neighbors[b].0.push(a);
+            if h.twin.is_none() {
+                neighbors[a].1.push(b);
+                neighbors[b].1.push(a);
+            }
+        }
+        neighbors
+    }
+
+    // New mesh whose vertices are the weighted sums of old ones in `stencils`
+    fn refine(&self, stencils: &[Vec<(usize, f32)>], faces: &[Vec<usize>]) -> Result<Self, String> {
+        let combine = |values: &[Vector3<f32>], stencil: &[(usize, f32)]| {
+            stencil
+                .iter()
+                .map(|&(v, w)| values[v] * w)
+                .sum::<Vector3<f32>>()
+        };
+        let positions = stencils
+            .iter()
+            .map(|s| combine(&self.positions, s))
+            .collect();
+        let mut mesh = Self::from_polygons(positions, faces)?;
+        if !self.colors.is_empty() {
+            mesh.colors = stencils.iter().map(|s| combine(&self.colors, s)).collect();
+        }
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tetrahedron() -> HalfEdgeMesh {
+        let positions = vec![
+            Vector3::new(1., 1., 1.),
+            Vector3::new(1., -1., -1.),
+            Vector3::new(-1., 1., -1.),
+            Vector3::new(-1., -1., 1.),
+        ];
+        let indices = [
+            Vector3::new(0, 1, 2),
+            Vector3::new(0, 3, 1),
+            Vector3::new(0, 2, 3),
+            Vector3::new(1, 3, 2),
+        ];
+        HalfEdgeMesh::from_triangles(positions, &indices).unwrap()
+    }
+
+    // Every half-edge has a twin pointing back and V - E + F = 2
+    fn assert_closed_sphere(mesh: &HalfEdgeMesh) {
+        for (he, half_edge) in mesh.half_edges().iter().enumerate() {
+            let twin = half_edge.twin.expect("boundary half-edge");
+            assert_eq!(mesh.half_edges()[twin].twin, Some(he));
+            assert_eq!(mesh.origin(twin), mesh.target(he));
+            assert_eq!(mesh.half_edges()[twin].edge, half_edge.edge);
+        }
+        let (v, e, f) = (mesh.positions.len(), mesh.edge_count(), mesh.face_count());
+        assert_eq!(v + f, e + 2);
+    }
+
+    #[test]
+    fn loop_subdivision_of_a_tetrahedron() {
+        let mesh = tetrahedron();
+        assert_closed_sphere(&mesh);
+
+        let once = mesh.loop_subdivide().unwrap();
+        assert_eq!(once.face_count(), 16);
+        assert_eq!(once.positions.len(), 10);
+        assert_eq!(once.edge_count(), 24);
+        assert!(once.is_triangle_mesh());
+        assert_closed_sphere(&once);
+
+        let twice = once.loop_subdivide().unwrap();
+        assert_eq!(twice.face_count(), 64);
+        assert_closed_sphere(&twice);
+    }
+
+    #[test]
+    fn catmull_clark_of_a_tetrahedron() {
+        let quads = tetrahedron().catmull_clark().unwrap();
+        // one quad per corner of every triangle
+        assert_eq!(quads.face_count(), 12);
+        assert!((0..quads.face_count()).all(|f| quads.face_vertices(f).len() == 4));
+        assert_closed_sphere(&quads);
+    }
+}
@@ -0,0 +1,261 @@
+#![allow(dead_code)]
+
+use crate::shadow::ShadowMap;
+use crate::transform::{look_at, orthographic, perspective};
+use crate::triangle::Triangle;
+use nalgebra::{Matrix4, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub intensity: Vector3<f32>,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3<f32>, intensity: Vector3<f32>) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+
+    // Direction towards the light and irradiance arriving at `point`
+    pub fn incident(&self, point: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let to_light = self.position - point;
+        (
+            to_light.normalize(),
+            self.intensity / to_light.norm_squared(),
+        )
+    }
+
+    // Diffuse + specular contribution at `point`, everything in the same space
+    pub fn blinn_phong(
+        &self,
+        point: &Vector3<f32>,
+        normal: &Vector3<f32>,
+        eye_pos: &Vector3<f32>,
+        kd: &Vector3<f32>,
+        material: &PhongMaterial,
+    ) -> Vector3<f32> {
+        let (l, irradiance) = self.incident(point);
+        blinn_phong(&l, &irradiance, point, normal, eye_pos, kd, material)
+    }
+}
+
+// Infinitely far light, `direction` points from the light into the scene
+pub struct DirectionalLight {
+    pub direction: Vector3<f32>,
+    pub intensity: Vector3<f32>,
+    pub shadow_map: Option<ShadowMap>,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3<f32>, intensity: Vector3<f32>) -> Self {
+        Self {
+            direction: direction.normalize(),
+            intensity,
+            shadow_map: None,
+        }
+    }
+
+    // Light camera looking at the sphere (`center`, `radius`) enclosing the scene
+    pub fn light_view(&self, center: &Vector3<f32>, radius: f32) -> Matrix4<f32> {
+        let eye = center - self.direction * 2. * radius;
+        look_at(&eye, center, &Vector3::y())
+    }
+
+    pub fn light_projection(&self, radius: f32) -> Matrix4<f32> {
+        orthographic(-radius, radius, -radius, radius, radius, 3. * radius)
+    }
+
+    pub fn cast_shadows(
+        &mut self,
+        center: &Vector3<f32>,
+        radius: f32,
+        size: usize,
+        model: Matrix4<f32>,
+        triangles: &[Triangle],
+    ) {
+        self.shadow_map = Some(ShadowMap::render(
+            size,
+            self.light_view(center, radius),
+            self.light_projection(radius),
+            model,
+            triangles,
+        ));
+    }
+}
+
+// Point light restricted to a cone, angles are in degrees
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub intensity: Vector3<f32>,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub shadow_map: Option<ShadowMap>,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vector3<f32>,
+        direction: Vector3<f32>,
+        intensity: Vector3<f32>,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            inner_angle,
+            outer_angle,
+            shadow_map: None,
+        }
+    }
+
+    pub fn incident(&self, point: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let to_light = self.position - point;
+        let l = to_light.normalize();
+
+        // smooth falloff between the inner and outer cone
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+        let t = ((-l).dot(&self.direction) - cos_outer) / (cos_inner - cos_outer);
+        let t = t.clamp(0., 1.);
+        let cone = t * t * (3. - 2. * t);
+
+        (l, self.intensity * cone / to_light.norm_squared())
+    }
+
+    pub fn light_view(&self) -> Matrix4<f32> {
+        look_at(
+            &self.position,
+            &(self.position + self.direction),
+            &Vector3::y(),
+        )
+    }
+
+    pub fn light_projection(&self, z_near: f32, z_far: f32) -> Matrix4<f32> {
+        perspective(2. * self.outer_angle, 1., z_near, z_far)
+    }
+
+    // Only casters between `z_near` and `z_far` from the light end up in the
+    // map, keep the range tight for depth precision
+    pub fn cast_shadows(
+        &mut self,
+        z_near: f32,
+        z_far: f32,
+        size: usize,
+        model: Matrix4<f32>,
+        triangles: &[Triangle],
+    ) {
+        self.shadow_map = Some(ShadowMap::render(
+            size,
+            self.light_view(),
+            self.light_projection(z_near, z_far),
+            model,
+            triangles,
+        ));
+    }
+}
+
+pub enum Light {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl Light {
+    pub fn incident(&self, point: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            Light::Point(light) => light.incident(point),
+            Light::Directional(light) => (-light.direction, light.intensity),
+            Light::Spot(light) => light.incident(point),
+        }
+    }
+
+    // Distance from `point` to the light, infinite for directional lights
+    pub fn distance(&self, point: &Vector3<f32>) -> f32 {
+        match self {
+            Light::Point(light) => (light.position - point).norm(),
+            Light::Directional(_) => f32::INFINITY,
+            Light::Spot(light) => (light.position - point).norm(),
+        }
+    }
+
+    pub fn shadow_map(&self) -> Option<&ShadowMap> {
+        match self {
+            Light::Point(_) => None,
+            Light::Directional(light) => light.shadow_map.as_ref(),
+            Light::Spot(light) => light.shadow_map.as_ref(),
+        }
+    }
+
+    // Shadowed Blinn-Phong, everything in world space
+    pub fn blinn_phong(
+        &self,
+        point: &Vector3<f32>,
+        normal: &Vector3<f32>,
+        eye_pos: &Vector3<f32>,
+        kd: &Vector3<f32>,
+        material: &PhongMaterial,
+    ) -> Vector3<f32> {
+        let (l, irradiance) = self.incident(point);
+        let visibility = self
+            .shadow_map()
+            .map_or(1., |shadow| shadow.visibility(point, normal.dot(&l)));
+        if visibility == 0. {
+            return Vector3::zeros();
+        }
+        blinn_phong(&l, &irradiance, point, normal, eye_pos, kd, material) * visibility
+    }
+}
+
+// `l` points towards the light and `irradiance` is what arrives at `point`
+pub fn blinn_phong(
+    l: &Vector3<f32>,
+    irradiance: &Vector3<f32>,
+    point: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    eye_pos: &Vector3<f32>,
+    kd: &Vector3<f32>,
+    material: &PhongMaterial,
+) -> Vector3<f32> {
+    let v = (eye_pos - point).normalize();
+    let h = (l + v).normalize();
+
+    let diffuse = kd.component_mul(irradiance) * normal.dot(l).max(0.);
+    let specular = material.ks.component_mul(irradiance) * normal.dot(&h).max(0.).powf(material.p);
+    diffuse + specular
+}
+
+// Per material-id coefficients, kd comes from the albedo of the geometry
+#[derive(Debug, Clone, Copy)]
+pub struct PhongMaterial {
+    pub ka: Vector3<f32>,
+    pub ks: Vector3<f32>,
+    pub p: f32,
+}
+
+impl PhongMaterial {
+    pub fn new(ka: Vector3<f32>, ks: Vector3<f32>, p: f32) -> Self {
+        Self { ka, ks, p }
+    }
+}
+
+impl Default for PhongMaterial {
+    fn default() -> Self {
+        Self {
+            ka: Vector3::new(0.005, 0.005, 0.005),
+            ks: Vector3::new(0.7937, 0.7937, 0.7937),
+            p: 150.,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+#![allow(dead_code)]
+
+use crate::rst::{ColBuf, IndBuf, PosBuf};
+use nalgebra::Vector3;
+
+pub struct LodLevel {
+    pub positions: PosBuf,
+    pub indices: IndBuf,
+    pub colors: ColBuf,
+    // Smallest projected diameter in pixels this level is drawn at
+    pub min_pixels: f32,
+}
+
+// Detail levels of one object, finest first, sharing a model space bounding
+// sphere. `select` remembers the level it picked last and only leaves it
+// once the size is `hysteresis` (a fraction) past the switching threshold,
+// so objects hovering around a threshold don't pop every frame.
+pub struct LodGroup {
+    levels: Vec<LodLevel>,
+    pub center: Vector3<f32>,
+    pub radius: f32,
+    pub hysteresis: f32,
+    current: usize,
+}
+
+impl LodGroup {
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self {
+            levels: Vec::new(),
+            center,
+            radius,
+            hysteresis: 0.1,
+            current: 0,
+        }
+    }
+
+    // Levels are kept ordered by `min_pixels`, largest (finest) first
+    pub fn add_level(
+        &mut self,
+        positions: PosBuf,
+        indices: IndBuf,
+        colors: ColBuf,
+        min_pixels: f32,
+    ) {
+        let level = LodLevel {
+            positions,
+            indices,
+            colors,
+            min_pixels,
+        };
+        let at = self
+            .levels
+            .iter()
+            .position(|l| l.min_pixels < min_pixels)
+            .unwrap_or(self.levels.len());
+        self.levels.insert(at, level);
+        self.current = self.current.min(self.levels.len() - 1);
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // Level for an object `pixels` wide on screen, None without levels
+    pub fn select(&mut self, pixels: f32) -> Option<&LodLevel> {
+        if self.levels.is_empty() {
+            return None;
+        }
+        // coarser while the current level is too detailed for the size
+        while self.current + 1 < self.levels.len()
+            && pixels < self.levels[self.current].min_pixels * (1. - self.hysteresis)
+        {
+            self.current += 1;
+        }
+        // finer while the next finer level clearly fits
+        while self.current > 0
+            && pixels > self.levels[self.current - 1].min_pixels * (1. + self.hysteresis)
+        {
+            self.current -= 1;
+        }
+        self.levels.get(self.current)
+    }
+}
+
+// Sphere around the centroid reaching the farthest point, not minimal but
+// cheap and stable
+pub fn bounding_sphere(positions: &[Vector3<f32>]) -> (Vector3<f32>, f32) {
+    if positions.is_empty() {
+        return (Vector3::zeros(), 0.);
+    }
+    let center = positions.iter().sum::<Vector3<f32>>() / positions.len() as f32;
+    let radius = positions
+        .iter()
+        .map(|p| (p - center).norm())
+        .fold(0., f32::max);
+    (center, radius)
+}
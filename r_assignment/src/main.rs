@@ -1,67 +1,505 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
+use light::{DirectionalLight, Light, PointLight, SpotLight};
 use nalgebra::{Matrix4, Vector3};
-use opencv::{core::Mat, highgui, prelude::*};
+use opencv::{core::Mat, core::Vector, highgui, imgcodecs, prelude::*};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tonemap::{OutputStage, ToneMapping};
+mod animation;
+mod bezier_patch;
+mod bvh;
+mod camera;
+mod curve;
+mod curve_editor;
+mod environment;
+mod gbuffer;
+mod half_edge;
+mod light;
+mod lod;
+mod mass_spring;
+mod material;
+mod mesh;
+mod pathtracer;
+mod pbr;
+mod progressive;
+mod quaternion;
+mod random;
+mod ray;
+mod raytracer;
 mod rst;
+mod shader;
+mod shadow;
+mod simplify;
+mod ssao;
+mod texture;
+mod tonemap;
+mod transform;
 mod triangle;
 
-fn get_model_matrix(angle: f32) -> Matrix4<f32> {
-    let sina = (angle / 180.0 * std::f32::consts::PI).sin();
-    let cosa = (angle / 180.0 * std::f32::consts::PI).cos();
-    Matrix4::new(
-        cosa, -sina, 0.0, 0.0, sina, cosa, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
-    )
+// Axis aligned box with flat outward normals
+fn block(min: &Vector3<f32>, max: &Vector3<f32>) -> Vec<triangle::Triangle> {
+    let mut triangles = Vec::new();
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for (side, sign) in [(min, -1.), (max, 1.)] {
+            let corner = |a: &Vector3<f32>, b: &Vector3<f32>| {
+                let mut p = *side;
+                p[u] = a[u];
+                p[v] = b[v];
+                p
+            };
+            // counter-clockwise seen from outside
+            let mut quad = [
+                corner(min, min),
+                corner(max, min),
+                corner(max, max),
+                corner(min, max),
+            ];
+            if sign < 0. {
+                quad.reverse();
+            }
+            let mut normal = Vector3::zeros();
+            normal[axis] = sign;
+            for face in [[0, 1, 2], [0, 2, 3]] {
+                let mut t = triangle::Triangle::default();
+                for (j, &k) in face.iter().enumerate() {
+                    t.set_vertex(j, quad[k]).ok();
+                    t.set_normal(j, normal).ok();
+                    t.set_color(j, 255., 255., 255.).ok();
+                }
+                triangles.push(t);
+            }
+        }
+    }
+    triangles
+}
+
+// Copy of the scene lit by a square area light above it. The triangles stay
+// diffuse, the floor turns into brushed metal and a glass block stands on it.
+fn path_tracer(
+    triangles: &[triangle::Triangle],
+    model: &Matrix4<f32>,
+    floor: &[triangle::Triangle],
+) -> pathtracer::PathTracer {
+    let mut tracer = pathtracer::PathTracer::new();
+    tracer.add(
+        triangles,
+        model,
+        material::Material::Lambertian,
+        Vector3::zeros(),
+    );
+    tracer.add(
+        floor,
+        &Matrix4::identity(),
+        material::Material::Conductor { roughness: 0.3 },
+        Vector3::zeros(),
+    );
+    tracer.add(
+        &block(
+            &Vector3::new(-3., -1.5, -3.5),
+            &Vector3::new(-2., -0.5, -2.5),
+        ),
+        &Matrix4::identity(),
+        material::Material::Dielectric {
+            ior: 1.5,
+            roughness: 0.05,
+        },
+        Vector3::zeros(),
+    );
+
+    let corners = [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)].map(|(x, z)| Vector3::new(x, 3., z));
+    let light: Vec<triangle::Triangle> = [[0, 1, 2], [0, 2, 3]]
+        .iter()
+        .map(|face| {
+            let mut t = triangle::Triangle::default();
+            for (j, &k) in face.iter().enumerate() {
+                t.set_vertex(j, corners[k]).ok();
+                t.set_normal(j, -Vector3::y()).ok();
+                t.set_color(j, 255., 255., 255.).ok();
+            }
+            t
+        })
+        .collect();
+    tracer.add(
+        &light,
+        &Matrix4::identity(),
+        material::Material::Lambertian,
+        Vector3::new(20., 20., 20.),
+    );
+    tracer
 }
 
-fn get_view_matrix(eye_pos: Vector3<f32>) -> Matrix4<f32> {
-    Matrix4::new(
-        1.0,
-        0.0,
-        0.0,
-        -eye_pos[0],
-        0.0,
-        1.0,
-        0.0,
-        -eye_pos[1],
-        0.0,
-        0.0,
-        1.0,
-        -eye_pos[2],
-        0.0,
-        0.0,
-        0.0,
-        1.0,
+// One turn about `axis` every six seconds, swaying sideways on the way
+fn spin(axis: &Vector3<f32>) -> animation::Animation {
+    let mut spin = animation::Animation::new(true);
+    for (i, sway) in [0., 0.5, -0.5, 0.].into_iter().enumerate() {
+        spin.add_keyframe(animation::Keyframe::new(
+            i as f32 * 2.,
+            Vector3::new(sway, 0., 0.),
+            quaternion::Quaternion::from_axis_angle(axis, i as f32 * 120.),
+            Vector3::new(1., 1., 1.),
+        ));
+    }
+    spin
+}
+
+// Left click adds or drags control points, right click deletes them
+fn curve_editor() {
+    let mut r = rst::Rasterizer::new(700, 700, rst::AntiAliasing::None);
+    let editor = Arc::new(Mutex::new(curve_editor::CurveEditor::new()));
+
+    highgui::named_window("curves", highgui::WINDOW_AUTOSIZE).expect("Failed to create window");
+    let state = Arc::clone(&editor);
+    let height = r.height();
+    highgui::set_mouse_callback(
+        "curves",
+        Some(Box::new(move |event, x, y, _flags| {
+            let p = curve_editor::CurveEditor::to_screen(x, y, height);
+            let mut editor = state.lock().expect("Curve editor lock poisoned");
+            match event {
+                highgui::EVENT_LBUTTONDOWN => editor.press(p),
+                highgui::EVENT_MOUSEMOVE => editor.drag(p),
+                highgui::EVENT_LBUTTONUP => editor.release(),
+                highgui::EVENT_RBUTTONDOWN => editor.delete(p),
+                _ => {}
+            }
+        })),
     )
+    .expect("Failed to set mouse callback");
+
+    // curve colors are display values already
+    let mut output = OutputStage::new(ToneMapping::Clamp);
+    output.srgb = false;
+
+    let mut key = 0;
+    while key != 27 {
+        editor
+            .lock()
+            .expect("Curve editor lock poisoned")
+            .draw(&mut r);
+        let img_data = output.encode_bgr8(r.framebuffer());
+        let mat = Mat::from_slice(&img_data).expect("Failed to create Mat from slice");
+        let newsz = vec![700, 700];
+        let image = mat.reshape_nd(3, &newsz).expect("Failed to reshape Mat");
+        highgui::imshow("curves", &image).expect("Failed to show image");
+        key = highgui::wait_key(10).expect("Failed to read key");
+
+        let mut editor = editor.lock().expect("Curve editor lock poisoned");
+        if key == ('c' as i8).into() {
+            editor.show_polygon = !editor.show_polygon;
+        } else if key == ('l' as i8).into() {
+            editor.show_levels = !editor.show_levels;
+        } else if key == ('b' as i8).into() {
+            editor.kind = editor.kind.next();
+            println!("curve: {:?}", editor.kind);
+        } else if key == ('a' as i8).into() {
+            editor.antialiasing = !editor.antialiasing;
+        } else if key == ('[' as i8).into() {
+            editor.t = (editor.t - 0.05).max(0.);
+        } else if key == (']' as i8).into() {
+            editor.t = (editor.t + 0.05).min(1.);
+        } else if key == ('=' as i8).into() {
+            editor.degree += 1;
+        } else if key == ('-' as i8).into() {
+            editor.degree = (editor.degree - 1).max(1);
+        } else if key == ('x' as i8).into() {
+            editor.clear();
+        }
+    }
+}
+
+// Rope and cloth hanging from pinned masses, stepped every frame
+fn mass_spring() {
+    let mut r = rst::Rasterizer::new(700, 700, rst::AntiAliasing::None);
+    r.set_view(transform::translation(&Vector3::new(0., 0., -5.)));
+    r.set_projection(transform::perspective(45., 1., 0.1, 50.));
+    r.set_fragment_shader(shader::phong_fragment_shader);
+
+    let scene = || {
+        let rope = mass_spring::MassSpring::rope(
+            Vector3::new(-2.5, 1.5, 0.),
+            Vector3::new(-0.5, 1.5, 0.),
+            16,
+            0.05,
+            500.,
+            &[0],
+        );
+        // horizontal sheet pinned at its two far corners
+        let cloth = mass_spring::MassSpring::cloth(
+            Vector3::new(0.5, 1.5, -1.),
+            Vector3::new(2., 0., 0.),
+            Vector3::new(0., 0., 2.),
+            (16, 16),
+            0.05,
+            200.,
+            &[0, 15],
+        );
+        (rope, cloth)
+    };
+    let (mut rope, mut cloth) = scene();
+    let mut integrator = mass_spring::Integrator::SemiImplicitEuler;
+    let mut wireframe = false;
+    let mut paused = false;
+    let steps_per_frame = 64;
+    let dt = 1. / 60. / steps_per_frame as f32;
+
+    let output = OutputStage::new(ToneMapping::Clamp);
+    let mut key = 0;
+    while key != 27 {
+        if !paused {
+            for _ in 0..steps_per_frame {
+                rope.step(dt, integrator);
+                cloth.step(dt, integrator);
+            }
+        }
+
+        r.clear(rst::Buffers::Color | rst::Buffers::Depth);
+        let white = Vector3::new(255., 255., 255.);
+        if wireframe {
+            r.draw_lines(&cloth.positions(), &cloth.lines(), &white);
+        } else {
+            r.draw_triangles(&cloth.triangles(&Vector3::new(185., 217., 238.)));
+        }
+        r.draw_lines(&rope.positions(), &rope.lines(), &white);
+
+        let img_data = output.encode_bgr8(r.framebuffer());
+        let mat = Mat::from_slice(&img_data).expect("Failed to create Mat from slice");
+        let newsz = vec![700, 700];
+        let image = mat.reshape_nd(3, &newsz).expect("Failed to reshape Mat");
+        highgui::imshow("mass spring", &image).expect("Failed to show image");
+        key = highgui::wait_key(10).expect("Failed to read key");
+
+        if key == ('i' as i8).into() {
+            integrator = integrator.next();
+            println!("integrator: {integrator:?}");
+        } else if key == ('w' as i8).into() {
+            wireframe = !wireframe;
+        } else if key == (' ' as i8).into() {
+            paused = !paused;
+        } else if key == ('r' as i8).into() {
+            (rope, cloth) = scene();
+        }
+    }
+}
+
+// Shaders the patch viewer cycles through with 'f', as named on the command line
+const SHADERS: [&str; 6] = [
+    "phong",
+    "normal",
+    "bump",
+    "displacement",
+    "normal_map",
+    "pbr",
+];
+
+// The patch viewer only moves its camera along z, so view space directions
+// are world space ones for the environment
+fn fragment_shader(
+    name: &str,
+    environment: Option<&Rc<environment::EnvironmentMap>>,
+) -> Result<shader::FragmentShader, String> {
+    match name {
+        "phong" => Ok(Box::new(shader::phong_fragment_shader)),
+        "normal" => Ok(Box::new(shader::normal_fragment_shader)),
+        "bump" => Ok(Box::new(shader::bump_fragment_shader)),
+        "displacement" => Ok(Box::new(shader::displacement_fragment_shader)),
+        "normal_map" => Ok(Box::new(shader::normal_map_fragment_shader)),
+        "pbr" => {
+            // brushed metal under the same two view space lights as phong
+            let material = pbr::PbrMaterial::new(Vector3::new(1., 1., 1.), 1., 0.4);
+            let lights = vec![
+                PointLight::new(Vector3::new(20., 20., 20.), Vector3::new(500., 500., 500.)),
+                PointLight::new(Vector3::new(-20., 20., 0.), Vector3::new(500., 500., 500.)),
+            ];
+            let mut shader = pbr::PbrShader::new(material, lights);
+            if let Some(environment) = environment {
+                shader = shader.with_environment(Rc::clone(environment), &Matrix4::identity());
+            }
+            Ok(Box::new(pbr::pbr_fragment_shader(shader)))
+        }
+        _ => Err(format!("Unknown shader {name}")),
+    }
 }
 
-fn get_projection_matrx(eye_fov: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
-    let mut projection: Matrix4<f32> = Matrix4::identity();
-    let eye_fov_rad = eye_fov * std::f32::consts::PI / 180.;
+// Newell style patch file tessellated at several detail levels, the one
+// drawn follows the size on screen. The patch parameters are the texture
+// coordinates, bump and displacement read their height map from `texture`
+// and normal_map its tangent space normals. An HDR `environment` lights the
+// pbr shader and fills the background.
+fn bezier_patches(path: &str, shader_name: &str, texture: Option<&str>, environment: Option<&str>) {
+    let patches = match bezier_patch::load_patches(path) {
+        Ok(patches) => patches,
+        Err(e) => return eprintln!("{e}"),
+    };
+    let Some(mut shading) = SHADERS.iter().position(|&name| name == shader_name) else {
+        return eprintln!("Unknown shader {shader_name}, expected one of {SHADERS:?}");
+    };
+    let mut r = rst::Rasterizer::new(700, 700, rst::AntiAliasing::None);
+    r.set_projection(transform::perspective(45., 1., 0.1, 50.));
+    if let Some(name) = texture {
+        let texture = texture::Texture::new(name);
+        if texture.width == 0 || texture.height == 0 {
+            return eprintln!("Failed to read texture {name}");
+        }
+        r.set_texture(texture);
+    }
+    let environment = match environment.map(|name| environment::EnvironmentMap::load(name, 6)) {
+        Some(Ok(environment)) => Some(Rc::new(environment)),
+        Some(Err(e)) => return eprintln!("{e}"),
+        None => None,
+    };
+    match fragment_shader(SHADERS[shading], environment.as_ref()) {
+        Ok(shader) => r.set_fragment_shader(shader),
+        Err(e) => return eprintln!("{e}"),
+    }
+
+    // (tolerance, smallest size in pixels) from fine to coarse
+    let detail = [(0.002, 400.), (0.01, 200.), (0.05, 80.), (0.25, 0.)];
+    let mut group = lod::LodGroup::new(Vector3::zeros(), 0.);
+    let mut smooth = Vec::new();
+    for (tolerance, min_pixels) in detail {
+        let mesh = bezier_patch::tessellate(&patches, tolerance);
+        println!(
+            "{} patches, {} triangles",
+            patches.len(),
+            mesh.indices.len()
+        );
+        if smooth.is_empty() {
+            (group.center, group.radius) = lod::bounding_sphere(&mesh.positions);
+        }
+        let colors = vec![Vector3::new(185., 217., 238.); mesh.positions.len()];
+        group.add_level(
+            r.load_positions(mesh.positions.clone()),
+            r.load_indices(mesh.indices.clone()),
+            r.load_colors(colors.clone()),
+            min_pixels,
+        );
+        // the buffers only give face normals, the mesh keeps the analytic ones
+        let mut mesh = mesh::Mesh { colors, ..mesh };
+        // tangents follow the patch parameters for the normal map
+        if !mesh.tex_coords.is_empty() {
+            mesh.generate_tangents()
+                .unwrap_or_else(|e| eprintln!("{e}"));
+        }
+        smooth.push(mesh.triangles().unwrap_or_default());
+    }
+    let mut analytic_normals = true;
 
-    // Calculate the top, bottom, right, and left values
-    let t = (eye_fov_rad / 2.).tan() * z_near;
-    let r = t * aspect_ratio;
-    let l = -r;
-    let b = -t;
+    // teapot data is z up
+    let upright = transform::rotation(&Vector3::x(), -90.);
+    let output = OutputStage::new(ToneMapping::Clamp);
+    let mut angle = 0.;
+    let mut distance = 10.;
+    let mut key = 0;
+    while key != 27 {
+        r.clear(rst::Buffers::Color | rst::Buffers::Depth);
+        r.set_model(upright * transform::rotation(&Vector3::z(), angle));
+        r.set_view(transform::translation(&Vector3::new(0., -1., -distance)));
+        let level = group.current();
+        if analytic_normals {
+            r.draw_lod_triangles(&mut group, &smooth).ok();
+        } else {
+            r.draw_lod(&mut group).ok();
+        }
+        // the background is whatever the geometry left at infinite depth
+        if let Some(environment) = &environment {
+            r.draw_skybox(environment);
+        }
+        if group.current() != level {
+            println!("detail level {}", group.current());
+        }
 
-    projection[(0, 0)] = 2. * z_near / (r - l);
-    projection[(1, 1)] = 2. * z_near / (t - b);
-    projection[(2, 2)] = -(z_far + z_near) / (z_far - z_near);
-    projection[(2, 3)] = -2. * z_far * z_near / (z_far - z_near);
-    projection[(3, 2)] = -1.;
-    projection[(3, 3)] = 0.;
+        let img_data = output.encode_bgr8(r.framebuffer());
+        let mat = Mat::from_slice(&img_data).expect("Failed to create Mat from slice");
+        let newsz = vec![700, 700];
+        let image = mat.reshape_nd(3, &newsz).expect("Failed to reshape Mat");
+        highgui::imshow("patches", &image).expect("Failed to show image");
+        key = highgui::wait_key(10).expect("Failed to read key");
 
-    projection
+        if key == ('a' as i8).into() {
+            angle += 10.0;
+        } else if key == ('d' as i8).into() {
+            angle -= 10.0;
+        } else if key == ('w' as i8).into() {
+            distance = f32::max(distance - 1., 4.);
+        } else if key == ('s' as i8).into() {
+            distance += 1.;
+        } else if key == ('n' as i8).into() {
+            analytic_normals = !analytic_normals;
+        } else if key == ('f' as i8).into() {
+            shading = (shading + 1) % SHADERS.len();
+            match fragment_shader(SHADERS[shading], environment.as_ref()) {
+                Ok(shader) => r.set_fragment_shader(shader),
+                Err(e) => eprintln!("{e}"),
+            }
+            println!("shader: {}", SHADERS[shading]);
+        }
+    }
 }
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("curves") {
+        return curve_editor();
+    }
+    if std::env::args().nth(1).as_deref() == Some("springs") {
+        return mass_spring();
+    }
+    if std::env::args().nth(1).as_deref() == Some("patches") {
+        // patches [file] [shader] [texture] [environment], the environment is
+        // told apart by its .hdr extension
+        let path = std::env::args().nth(2).unwrap_or("teapot.txt".to_string());
+        let shader = std::env::args().nth(3).unwrap_or("phong".to_string());
+        let (environment, texture): (Vec<String>, Vec<String>) = std::env::args()
+            .skip(4)
+            .partition(|arg| arg.ends_with(".hdr"));
+        return bezier_patches(
+            &path,
+            &shader,
+            texture.first().map(String::as_str),
+            environment.first().map(String::as_str),
+        );
+    }
+
     // Init rasterizer size
     let mut r = rst::Rasterizer::new(700, 700, rst::AntiAliasing::None);
 
-    let mut angle = 0.0;
+    // 'a' plays and pauses the animation
+    let mut axis = Vector3::z();
+    let mut animation = spin(&axis);
+    let mut animating = false;
+    let mut time = 0.;
+    let mut last_frame = Instant::now();
 
-    // camera position
-    let eye_pos = Vector3::new(0., 0., 5.);
+    // orbits around the middle of the scene, 'f' switches to first person
+    let camera = Arc::new(Mutex::new(camera::Camera::new(
+        Vector3::new(0., 0., 5.),
+        Vector3::new(0., 0., -3.5),
+        Vector3::y(),
+    )));
+    highgui::named_window("image", highgui::WINDOW_AUTOSIZE).expect("Failed to create window");
+    let state = Arc::clone(&camera);
+    let height = r.height() as f32;
+    highgui::set_mouse_callback(
+        "image",
+        Some(Box::new(move |event, x, y, flags| {
+            let mut camera = state.lock().expect("Camera lock poisoned");
+            match event {
+                highgui::EVENT_MOUSEMOVE => {
+                    let left = flags & highgui::EVENT_FLAG_LBUTTON != 0;
+                    let right = flags & highgui::EVENT_FLAG_RBUTTON != 0;
+                    camera.mouse_move(x, y, left, right, height);
+                }
+                highgui::EVENT_MOUSEWHEEL => {
+                    let delta = highgui::get_mouse_wheel_delta(flags).unwrap_or(0);
+                    camera.wheel(delta.signum() as f32);
+                }
+                _ => {}
+            }
+        })),
+    )
+    .expect("Failed to set mouse callback");
 
     let points = [
         (2., 0., -2.),
@@ -89,9 +527,65 @@ fn main() {
     .map(|&(r, g, b)| Vector3::new(r, g, b))
     .collect();
 
-    let pos_id = r.load_positions(points);
-    let ind_id = r.load_indices(ind);
-    let col_id = r.load_colors(colors);
+    let mut pos_id = r.load_positions(points);
+    let mut ind_id = r.load_indices(ind);
+    let mut col_id = r.load_colors(colors);
+
+    // static floor below the animated triangles, drawn with its own material
+    let floor_pos_id = r.load_positions(
+        [
+            (-4., -1.5, -1.),
+            (4., -1.5, -1.),
+            (4., -1.5, -7.),
+            (-4., -1.5, -7.),
+        ]
+        .iter()
+        .map(|&(x, y, z)| Vector3::new(x, y, z))
+        .collect(),
+    );
+    let floor_ind_id = r.load_indices(vec![Vector3::new(0, 1, 2), Vector3::new(0, 2, 3)]);
+    let floor_col_id = r.load_colors(vec![Vector3::new(200., 200., 200.); 4]);
+    let floor_material = light::PhongMaterial::new(
+        Vector3::new(0.005, 0.005, 0.005),
+        Vector3::new(0.1, 0.1, 0.1),
+        10.,
+    );
+    let glossy = r.add_material(light::PhongMaterial::default());
+    let matte = r.add_material(floor_material);
+
+    // ring of point lights plus a shadow casting sun and spot light for the
+    // deferred mode
+    let mut lights: Vec<Light> = (0..24)
+        .map(|i| {
+            let a = i as f32 / 24. * 2. * std::f32::consts::PI;
+            PointLight::new(
+                Vector3::new(6. * a.cos(), 6. * a.sin(), 4.),
+                Vector3::new(1.5, 1.5, 1.5),
+            )
+            .into()
+        })
+        .collect();
+    lights.push(Light::Directional(DirectionalLight::new(
+        Vector3::new(-0.2, -0.3, -1.),
+        Vector3::new(0.5, 0.5, 0.5),
+    )));
+    lights.push(Light::Spot(SpotLight::new(
+        Vector3::new(0., 4., -1.),
+        Vector3::new(0., -5.5, -2.5),
+        Vector3::new(15., 15., 15.),
+        20.,
+        30.,
+    )));
+    let mut deferred = false;
+    let mut ambient_occlusion = false;
+    let mut output = OutputStage::new(ToneMapping::Clamp);
+    let mut interpolation = 2;
+    let mut perspective_correct = true;
+    let mut ray_traced = false;
+    let mut path_traced = false;
+    let mut paused = false;
+    let mut tracer: Option<pathtracer::PathTracer> = None;
+    let mut accumulator = progressive::Accumulator::new(r.width(), r.height());
 
     // keyboard input
     let mut key = 0;
@@ -101,21 +595,89 @@ fn main() {
     while key != 27 {
         // clear depth buffer and color buffer
         r.clear(rst::Buffers::Color | rst::Buffers::Depth);
-        r.set_model(get_model_matrix(angle));
-        r.set_view(get_view_matrix(eye_pos));
-        r.set_projection(get_projection_matrx(45., 1., 0.1, 50.));
+        let (view, projection) = {
+            let camera = camera.lock().expect("Camera lock poisoned");
+            (camera.view(), camera.projection())
+        };
+        let model = animation.model(time);
+        r.set_view(view);
+        r.set_projection(projection);
+        r.set_model(model);
+        r.set_material_id(glossy);
         r.draw(&pos_id, &ind_id, &col_id, rst::Primitive::Triangle)
             .ok();
-
-        // Assume frame_buffer is Vec<Vector3f> with RGB floats in [0,
-        let mut img_data = Vec::with_capacity(700 * 700 * 3);
-
-        for pixel in r.framebuffer() {
-            img_data.push((pixel.z) as u8); // B
-            img_data.push((pixel.y) as u8); // G
-            img_data.push((pixel.x) as u8); // R
+        r.set_model(Matrix4::identity());
+        r.set_material_id(matte);
+        r.draw(
+            &floor_pos_id,
+            &floor_ind_id,
+            &floor_col_id,
+            rst::Primitive::Triangle,
+        )
+        .ok();
+        if deferred {
+            let triangles = r.triangles(&pos_id, &ind_id, &col_id).unwrap_or_default();
+            for light in lights.iter_mut() {
+                match light {
+                    Light::Directional(sun) => {
+                        let center = Vector3::new(0.5, 0.5, -3.5);
+                        sun.cast_shadows(&center, 5., 512, model, &triangles);
+                    }
+                    Light::Spot(spot) => spot.cast_shadows(1., 12., 512, model, &triangles),
+                    Light::Point(_) => {}
+                }
+            }
+            r.shade_deferred(&lights);
         }
 
+        // frame_buffer holds linear RGB with 255 as white
+        let img_data = if ray_traced {
+            let triangles = r.triangles(&pos_id, &ind_id, &col_id).unwrap_or_default();
+            let floor = r
+                .triangles(&floor_pos_id, &floor_ind_id, &floor_col_id)
+                .unwrap_or_default();
+            let mut scene = raytracer::Scene::new();
+            scene.add(
+                &triangles,
+                &model,
+                raytracer::WhittedMaterial::DiffuseAndGlossy(light::PhongMaterial::default()),
+            );
+            // the floor turns into a mirror showing the triangles upside down
+            scene.add(
+                &floor,
+                &Matrix4::identity(),
+                raytracer::WhittedMaterial::Reflection,
+            );
+            let frame_buf = scene.render(&lights, r.width(), r.height(), &view, &projection);
+            output.encode_bgr8(&frame_buf)
+        } else if path_traced {
+            // one sample per pixel and frame, the window shows the running average
+            let tracer = tracer.get_or_insert_with(|| {
+                let triangles = r.triangles(&pos_id, &ind_id, &col_id).unwrap_or_default();
+                let floor = r
+                    .triangles(&floor_pos_id, &floor_ind_id, &floor_col_id)
+                    .unwrap_or_default();
+                path_tracer(&triangles, &model, &floor)
+            });
+            // orbiting or panning since the last pass starts over
+            accumulator.set_camera(&view, &projection);
+            if !paused {
+                let frame_buf = tracer.render_pass(
+                    r.width(),
+                    r.height(),
+                    &view,
+                    &projection,
+                    1,
+                    accumulator.samples() as u64,
+                );
+                accumulator.add(&frame_buf, 1).ok();
+                println!("samples per pixel: {}", accumulator.samples());
+            }
+            output.encode_bgr8(&accumulator.average())
+        } else {
+            output.encode_bgr8(r.framebuffer())
+        };
+
         let mat = Mat::from_slice(&img_data).expect("Failed to create Mat from slice");
         let newsz = vec![700, 700];
         let image = mat.reshape_nd(3, &newsz).expect("Failed to reshape Mat");
@@ -124,10 +686,139 @@ fn main() {
 
         println!("frame count: {frame_count}");
         frame_count += 1;
-        if key == ('a' as i8).into() {
-            angle += 10.0;
-        } else if key == ('d' as i8).into() {
-            angle -= 10.0;
+        let previous_axis = axis;
+        let mut camera = camera.lock().expect("Camera lock poisoned");
+        let walking = camera.mode == camera::CameraMode::FirstPerson;
+        if key == ('f' as i8).into() {
+            camera.mode = match camera.mode {
+                camera::CameraMode::Orbit => camera::CameraMode::FirstPerson,
+                camera::CameraMode::FirstPerson => camera::CameraMode::Orbit,
+            };
+            println!("camera: {:?}", camera.mode);
+        } else if walking && key == ('w' as i8).into() {
+            camera.walk(1., 0., 0.);
+        } else if walking && key == ('s' as i8).into() {
+            camera.walk(-1., 0., 0.);
+        } else if walking && key == ('a' as i8).into() {
+            camera.walk(0., -1., 0.);
+        } else if walking && key == ('d' as i8).into() {
+            camera.walk(0., 1., 0.);
+        } else if walking && key == ('q' as i8).into() {
+            camera.walk(0., 0., -1.);
+        } else if walking && key == ('e' as i8).into() {
+            camera.walk(0., 0., 1.);
+        } else if key == ('a' as i8).into() {
+            animating = !animating;
+        } else if key == ('z' as i8).into() {
+            // cycles the rotation axis through x, y, z and a diagonal
+            axis = if axis == Vector3::z() {
+                Vector3::x()
+            } else if axis == Vector3::x() {
+                Vector3::y()
+            } else if axis == Vector3::y() {
+                Vector3::new(1., 1., 1.)
+            } else {
+                Vector3::z()
+            };
+            animation = spin(&axis);
+            println!("rotation axis: {:?}", axis.as_slice());
+        } else if key == ('v' as i8).into() {
+            camera.projection = camera.projection.next();
+            println!("projection: {:?}", camera.projection);
+        } else if key == ('u' as i8).into() || key == ('k' as i8).into() {
+            let positions = r.positions(&pos_id).cloned().unwrap_or_default();
+            let indices = r.indices(&ind_id).cloned().unwrap_or_default();
+            let colors = r.colors(&col_id).cloned().unwrap_or_default();
+            let subdivided = half_edge::HalfEdgeMesh::from_triangles(positions, &indices).and_then(
+                |mut mesh| {
+                    mesh.set_colors(colors)?;
+                    if key == ('u' as i8).into() {
+                        mesh.loop_subdivide()
+                    } else {
+                        mesh.catmull_clark()
+                    }
+                },
+            );
+            match subdivided {
+                Ok(mesh) => {
+                    println!("subdivided to {} faces", mesh.face_count());
+                    pos_id = r.load_positions(mesh.positions.clone());
+                    ind_id = r.load_indices(mesh.triangles());
+                    col_id = r.load_colors(mesh.colors);
+                    tracer = None;
+                    accumulator.reset();
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        } else if key == ('j' as i8).into() {
+            let positions = r.positions(&pos_id).cloned().unwrap_or_default();
+            let indices = r.indices(&ind_id).cloned().unwrap_or_default();
+            let colors = r.colors(&col_id).cloned().unwrap_or_default();
+            let target = simplify::Target::TriangleCount(indices.len() / 2);
+            match simplify::simplify(&positions, &indices, target) {
+                Ok(mesh) => {
+                    println!("simplified to {} triangles", mesh.indices.len());
+                    let colors = mesh.vertices.iter().map(|&v| colors[v]).collect();
+                    pos_id = r.load_positions(mesh.positions);
+                    ind_id = r.load_indices(mesh.indices);
+                    col_id = r.load_colors(colors);
+                    tracer = None;
+                    accumulator.reset();
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        } else if key == ('x' as i8).into() {
+            path_traced = !path_traced;
+        } else if key == (' ' as i8).into() {
+            paused = !paused;
+        } else if key == ('c' as i8).into() {
+            // 's' walks backwards in first person, 'c' captures in both modes
+            imgcodecs::imwrite("output.png", &image, &Vector::new()).expect("Failed to save image");
+            println!("saved output.png");
+        } else if key == ('m' as i8).into() {
+            deferred = !deferred;
+            r.set_render_mode(if deferred {
+                rst::RenderMode::Deferred
+            } else {
+                rst::RenderMode::Forward
+            });
+        } else if key == ('o' as i8).into() {
+            ambient_occlusion = !ambient_occlusion;
+            r.set_ssao(ambient_occlusion.then(|| ssao::Ssao::new(16, 0.5, 2)));
+        } else if key == ('i' as i8).into() {
+            interpolation = (interpolation + 1) % 3;
+            r.set_interpolation(match interpolation {
+                0 => rst::ShadingInterpolation::Flat,
+                1 => rst::ShadingInterpolation::Gouraud,
+                _ => rst::ShadingInterpolation::Phong,
+            });
+        } else if key == ('p' as i8).into() {
+            perspective_correct = !perspective_correct;
+            r.set_perspective_correct(perspective_correct);
+        } else if key == ('r' as i8).into() {
+            ray_traced = !ray_traced;
+        } else if key == ('t' as i8).into() {
+            output.tone_mapping = output.tone_mapping.next();
+            println!("tone mapping: {:?}", output.tone_mapping);
+        } else if key == ('g' as i8).into() {
+            output.srgb = !output.srgb;
+        } else if key == ('=' as i8).into() {
+            output.exposure *= 1.25;
+        } else if key == ('-' as i8).into() {
+            output.exposure /= 1.25;
+        }
+        let now = Instant::now();
+        let mut model_changed = axis != previous_axis;
+        // the clock stands still while tracing so samples keep accumulating
+        if animating && !ray_traced && !path_traced {
+            time += (now - last_frame).as_secs_f32();
+            model_changed = true;
+        }
+        last_frame = now;
+        if model_changed {
+            // the accumulated samples belong to the old view
+            tracer = None;
+            accumulator.reset();
         }
     }
 }
@@ -0,0 +1,263 @@
+#![allow(dead_code)]
+
+use crate::triangle::Triangle;
+use nalgebra::Vector3;
+
+#[derive(Debug, Clone)]
+pub struct Mass {
+    pub position: Vector3<f32>,
+    // previous position, only used by Verlet integration
+    pub last_position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    // accumulated during a step
+    pub forces: Vector3<f32>,
+    pub mass: f32,
+    // pinned masses never move
+    pub pinned: bool,
+}
+
+impl Mass {
+    pub fn new(position: Vector3<f32>, mass: f32, pinned: bool) -> Self {
+        Self {
+            position,
+            last_position: position,
+            velocity: Vector3::zeros(),
+            forces: Vector3::zeros(),
+            mass,
+            pinned,
+        }
+    }
+}
+
+// Hooke's law spring between two masses
+#[derive(Debug, Clone, Copy)]
+pub struct Spring {
+    pub a: usize,
+    pub b: usize,
+    pub rest_length: f32,
+    pub k: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    // Position from the old velocity, unstable for stiff springs
+    ExplicitEuler,
+    // Velocity first, position from the new velocity
+    SemiImplicitEuler,
+    // Position from the last two positions, velocity is implicit
+    Verlet,
+}
+
+impl Integrator {
+    pub fn next(self) -> Self {
+        match self {
+            Integrator::ExplicitEuler => Integrator::SemiImplicitEuler,
+            Integrator::SemiImplicitEuler => Integrator::Verlet,
+            Integrator::Verlet => Integrator::ExplicitEuler,
+        }
+    }
+}
+
+// Masses connected by springs, either a rope (GAMES101 assignment 8) or a
+// rectangular cloth grid
+#[derive(Debug, Clone)]
+pub struct MassSpring {
+    pub masses: Vec<Mass>,
+    pub springs: Vec<Spring>,
+    pub gravity: Vector3<f32>,
+    // Fraction of the velocity lost per second, independent of the mass
+    pub damping: f32,
+    // cloth triangles, counter-clockwise seen from the front
+    faces: Vec<[usize; 3]>,
+}
+
+impl MassSpring {
+    // `nodes` masses evenly spaced from `start` to `end`
+    pub fn rope(
+        start: Vector3<f32>,
+        end: Vector3<f32>,
+        nodes: usize,
+        node_mass: f32,
+        k: f32,
+        pinned: &[usize],
+    ) -> Self {
+        let nodes = nodes.max(2);
+        let masses = (0..nodes)
+            .map(|i| {
+                let p = start.lerp(&end, i as f32 / (nodes - 1) as f32);
+                Mass::new(p, node_mass, pinned.contains(&i))
+            })
+            .collect();
+        let mut system = Self::new(masses, Vec::new());
+        for i in 1..nodes {
+            system.connect(i - 1, i, k);
+        }
+        system
+    }
+
+    // `cols` x `rows` grid spanned by `u` and `v` from `origin`, mass
+    // `row * cols + col` sits at origin + u * col / (cols - 1) + v * row / (rows - 1).
+    // Structural, shear and bending springs keep the sheet together.
+    pub fn cloth(
+        origin: Vector3<f32>,
+        u: Vector3<f32>,
+        v: Vector3<f32>,
+        (cols, rows): (usize, usize),
+        node_mass: f32,
+        k: f32,
+        pinned: &[usize],
+    ) -> Self {
+        let (cols, rows) = (cols.max(2), rows.max(2));
+        let index = |col: usize, row: usize| row * cols + col;
+        let mut masses = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            for col in 0..cols {
+                let p = origin
+                    + u * (col as f32 / (cols - 1) as f32)
+                    + v * (row as f32 / (rows - 1) as f32);
+                masses.push(Mass::new(p, node_mass, pinned.contains(&index(col, row))));
+            }
+        }
+
+        let mut faces = Vec::new();
+        for row in 0..rows - 1 {
+            for col in 0..cols - 1 {
+                let (a, b) = (index(col, row), index(col + 1, row));
+                let (c, d) = (index(col + 1, row + 1), index(col, row + 1));
+                faces.push([a, b, c]);
+                faces.push([a, c, d]);
+            }
+        }
+
+        let mut system = Self::new(masses, faces);
+        for row in 0..rows {
+            for col in 0..cols {
+                let i = index(col, row);
+                if col + 1 < cols {
+                    system.connect(i, index(col + 1, row), k);
+                }
+                if row + 1 < rows {
+                    system.connect(i, index(col, row + 1), k);
+                }
+                if col + 1 < cols && row + 1 < rows {
+                    system.connect(i, index(col + 1, row + 1), k);
+                    system.connect(index(col + 1, row), index(col, row + 1), k);
+                }
+                // bending springs resist folding, kept softer
+                if col + 2 < cols {
+                    system.connect(i, index(col + 2, row), k * 0.5);
+                }
+                if row + 2 < rows {
+                    system.connect(i, index(col, row + 2), k * 0.5);
+                }
+            }
+        }
+        system
+    }
+
+    fn new(masses: Vec<Mass>, faces: Vec<[usize; 3]>) -> Self {
+        Self {
+            masses,
+            springs: Vec::new(),
+            gravity: Vector3::new(0., -9.8, 0.),
+            damping: 0.5,
+            faces,
+        }
+    }
+
+    // Adds a spring at rest in the current configuration
+    pub fn connect(&mut self, a: usize, b: usize, k: f32) {
+        let rest_length = (self.masses[b].position - self.masses[a].position).norm();
+        self.springs.push(Spring {
+            a,
+            b,
+            rest_length,
+            k,
+        });
+    }
+
+    pub fn step(&mut self, dt: f32, integrator: Integrator) {
+        for m in &mut self.masses {
+            m.forces = m.mass * self.gravity;
+        }
+        for s in &self.springs {
+            let d = self.masses[s.b].position - self.masses[s.a].position;
+            let length = d.norm();
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let f = d / length * (s.k * (length - s.rest_length));
+            self.masses[s.a].forces += f;
+            self.masses[s.b].forces -= f;
+        }
+
+        for m in &mut self.masses {
+            if m.pinned {
+                continue;
+            }
+            let a = m.forces / m.mass;
+            let damping = (1. - self.damping * dt).max(0.);
+            match integrator {
+                Integrator::ExplicitEuler => {
+                    m.position += m.velocity * dt;
+                    m.velocity = (m.velocity + a * dt) * damping;
+                }
+                Integrator::SemiImplicitEuler => {
+                    m.velocity = (m.velocity + a * dt) * damping;
+                    m.position += m.velocity * dt;
+                }
+                Integrator::Verlet => {
+                    let p = m.position;
+                    m.position += damping * (p - m.last_position) + a * dt * dt;
+                    m.velocity = (m.position - p) / dt;
+                    m.last_position = p;
+                }
+            }
+            if integrator != Integrator::Verlet {
+                m.last_position = m.position - m.velocity * dt;
+            }
+        }
+    }
+
+    pub fn positions(&self) -> Vec<Vector3<f32>> {
+        self.masses.iter().map(|m| m.position).collect()
+    }
+
+    // Mass index pairs of every spring, for `Rasterizer::draw_lines`
+    pub fn lines(&self) -> Vec<[usize; 2]> {
+        self.springs.iter().map(|s| [s.a, s.b]).collect()
+    }
+
+    // Cloth triangles with smooth normals and `color` in [0, 255]. The
+    // rasterizer culls back faces, so every face is emitted with both
+    // windings to keep the cloth visible from either side.
+    pub fn triangles(&self, color: &Vector3<f32>) -> Vec<Triangle> {
+        let mut normals = vec![Vector3::<f32>::zeros(); self.masses.len()];
+        for f in &self.faces {
+            let p = f.map(|i| self.masses[i].position);
+            let n = (p[1] - p[0]).cross(&(p[2] - p[0]));
+            for &i in f {
+                normals[i] += n;
+            }
+        }
+        let normals: Vec<Vector3<f32>> = normals
+            .iter()
+            .map(|n| n.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z))
+            .collect();
+
+        let mut triangles = Vec::with_capacity(self.faces.len() * 2);
+        for f in &self.faces {
+            for (order, side) in [([0, 1, 2], 1.), ([0, 2, 1], -1.)] {
+                let mut t = Triangle::default();
+                for (j, &k) in order.iter().enumerate() {
+                    let i = f[k];
+                    t.set_vertex(j, self.masses[i].position).ok();
+                    t.set_normal(j, normals[i] * side).ok();
+                    t.set_color(j, color.x, color.y, color.z).ok();
+                }
+                triangles.push(t);
+            }
+        }
+        triangles
+    }
+}
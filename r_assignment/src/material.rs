@@ -0,0 +1,425 @@
+#![allow(dead_code)]
+
+use crate::environment::importance_sample_ggx;
+use crate::random::Rng;
+use nalgebra::{Vector2, Vector3};
+use std::f32::consts::PI;
+
+// Scattering models of the path tracer. The color a material is evaluated
+// with comes from the triangle colors, in [0, 1] like `Triangle::color`.
+//
+// Directions point away from the surface: `wo` towards the viewer and `wi`
+// towards the light. `n` is the outward normal of the triangle, rays may
+// arrive from either side.
+#[derive(Debug, Clone, Copy)]
+pub enum Material {
+    // Ideal diffuse reflector, color is the albedo
+    Lambertian,
+    // GGX microfacet metal, color is the reflectance at normal incidence
+    Conductor { roughness: f32 },
+    // GGX microfacet glass (Walter et al. 2007), color tints the transmission
+    Dielectric { ior: f32, roughness: f32 },
+    // Perfect specular reflection tinted by color
+    Mirror,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    pub wi: Vector3<f32>,
+    // bsdf * |cos| / pdf
+    pub weight: Vector3<f32>,
+    pub pdf: f32,
+    // sampled from a delta distribution, `eval` and `pdf` are zero for it
+    pub specular: bool,
+}
+
+impl Material {
+    // Delta distributions are only reachable through `sample`, light sampling
+    // can not contribute to them
+    pub fn is_specular(&self) -> bool {
+        matches!(self, Material::Mirror)
+    }
+
+    // Value of the bsdf, without the cosine term
+    pub fn eval(
+        &self,
+        color: &Vector3<f32>,
+        n: &Vector3<f32>,
+        wo: &Vector3<f32>,
+        wi: &Vector3<f32>,
+    ) -> Vector3<f32> {
+        let cos_o = n.dot(wo);
+        let cos_i = n.dot(wi);
+        if cos_o == 0. || cos_i == 0. {
+            return Vector3::zeros();
+        }
+        let reflect = cos_o * cos_i > 0.;
+        match *self {
+            Material::Lambertian if reflect => color / PI,
+            Material::Conductor { roughness } if reflect => {
+                let alpha = alpha(roughness);
+                let m = half_vector(n, wo, wi);
+                let f = schlick(color, wo.dot(&m).abs());
+                f * (ggx_d(n, &m, alpha) * smith_g(n, wo, wi, alpha)
+                    / (4. * cos_o.abs() * cos_i.abs()))
+            }
+            Material::Dielectric { ior, roughness } => {
+                let alpha = alpha(roughness);
+                let Some((m, etap)) = dielectric_half_vector(n, wo, wi, ior) else {
+                    return Vector3::zeros();
+                };
+                let f = fresnel_dielectric(wo.dot(&m), ior);
+                let dg = ggx_d(n, &m, alpha) * smith_g(n, wo, wi, alpha);
+                if reflect {
+                    Vector3::repeat(dg * f / (4. * cos_o.abs() * cos_i.abs()))
+                } else {
+                    let denom = (wi.dot(&m) + wo.dot(&m) / etap).powi(2) * cos_i * cos_o;
+                    // radiance is compressed into the denser medium
+                    let ft =
+                        dg * (1. - f) * (wi.dot(&m) * wo.dot(&m) / denom).abs() / (etap * etap);
+                    color * ft
+                }
+            }
+            _ => Vector3::zeros(),
+        }
+    }
+
+    // Solid angle density of `sample` producing `wi`
+    pub fn pdf(&self, n: &Vector3<f32>, wo: &Vector3<f32>, wi: &Vector3<f32>) -> f32 {
+        let cos_o = n.dot(wo);
+        let cos_i = n.dot(wi);
+        if cos_o == 0. || cos_i == 0. {
+            return 0.;
+        }
+        let reflect = cos_o * cos_i > 0.;
+        match *self {
+            Material::Lambertian if reflect => cos_i.abs() / PI,
+            Material::Conductor { roughness } if reflect => {
+                let m = half_vector(n, wo, wi);
+                ggx_d(n, &m, alpha(roughness)) * n.dot(&m).abs() / (4. * wo.dot(&m).abs())
+            }
+            Material::Dielectric { ior, roughness } => {
+                let Some((m, etap)) = dielectric_half_vector(n, wo, wi, ior) else {
+                    return 0.;
+                };
+                let f = fresnel_dielectric(wo.dot(&m), ior);
+                let pdf_m = ggx_d(n, &m, alpha(roughness)) * n.dot(&m).abs();
+                if reflect {
+                    pdf_m / (4. * wo.dot(&m).abs()) * f
+                } else {
+                    let denom = (wi.dot(&m) + wo.dot(&m) / etap).powi(2);
+                    pdf_m * wi.dot(&m).abs() / denom * (1. - f)
+                }
+            }
+            _ => 0.,
+        }
+    }
+
+    pub fn sample(
+        &self,
+        color: &Vector3<f32>,
+        n: &Vector3<f32>,
+        wo: &Vector3<f32>,
+        rng: &mut Rng,
+    ) -> Option<BsdfSample> {
+        let cos_o = n.dot(wo);
+        if cos_o == 0. {
+            return None;
+        }
+        // normal on the side of the viewer
+        let ns = if cos_o > 0. { *n } else { -n };
+
+        let wi = match *self {
+            Material::Lambertian => cosine_sample_hemisphere(&ns, rng),
+            Material::Conductor { roughness } => {
+                let m = sample_microfacet(&ns, roughness, rng);
+                if wo.dot(&m) <= 0. {
+                    return None;
+                }
+                reflect(wo, &m)
+            }
+            Material::Dielectric { ior, roughness } => {
+                let m = sample_microfacet(&ns, roughness, rng);
+                if wo.dot(&m) <= 0. {
+                    return None;
+                }
+                // measured against the outward facing side of the interface
+                let f = fresnel_dielectric(wo.dot(&m) * cos_o.signum(), ior);
+                let reflected = rng.next_f32() < f;
+                let wi = if reflected {
+                    reflect(wo, &m)
+                } else {
+                    // entering when the viewer is outside
+                    let eta = if cos_o > 0. { ior } else { 1. / ior };
+                    refract(wo, &m, eta)?
+                };
+                // steep microfacets may send the ray to the wrong side
+                if (n.dot(&wi) * cos_o > 0.) != reflected {
+                    return None;
+                }
+                wi
+            }
+            Material::Mirror => {
+                return Some(BsdfSample {
+                    wi: reflect(wo, &ns),
+                    weight: *color,
+                    pdf: 1.,
+                    specular: true,
+                });
+            }
+        };
+
+        let pdf = self.pdf(n, wo, &wi);
+        if pdf <= 0. {
+            return None;
+        }
+        let weight = self.eval(color, n, wo, &wi) * n.dot(&wi).abs() / pdf;
+        Some(BsdfSample {
+            wi,
+            weight,
+            pdf,
+            specular: false,
+        })
+    }
+}
+
+// Direction around `n` with density cos(theta) / pi
+pub fn cosine_sample_hemisphere(n: &Vector3<f32>, rng: &mut Rng) -> Vector3<f32> {
+    let r = rng.next_f32().sqrt();
+    let phi = 2. * PI * rng.next_f32();
+    let local = Vector3::new(r * phi.cos(), r * phi.sin(), (1. - r * r).max(0.).sqrt());
+
+    let up = if n.z.abs() < 0.999 {
+        Vector3::z()
+    } else {
+        Vector3::x()
+    };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(&tangent);
+    (tangent * local.x + bitangent * local.y + n * local.z).normalize()
+}
+
+// Microfacet normal with density D(m) |n.m|
+fn sample_microfacet(n: &Vector3<f32>, roughness: f32, rng: &mut Rng) -> Vector3<f32> {
+    let xi = Vector2::new(rng.next_f32(), rng.next_f32());
+    // `importance_sample_ggx` squares the roughness itself
+    importance_sample_ggx(&xi, n, alpha(roughness).sqrt())
+}
+
+// Perceptual roughness to GGX alpha, kept away from the delta limit
+fn alpha(roughness: f32) -> f32 {
+    roughness.clamp(0.05, 1.).powi(2)
+}
+
+fn ggx_d(n: &Vector3<f32>, m: &Vector3<f32>, alpha: f32) -> f32 {
+    let cos = n.dot(m);
+    if cos <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    let d = cos * cos * (a2 - 1.) + 1.;
+    a2 / (PI * d * d)
+}
+
+// Separable Smith masking-shadowing for GGX
+fn smith_g(n: &Vector3<f32>, wo: &Vector3<f32>, wi: &Vector3<f32>, alpha: f32) -> f32 {
+    let g1 = |v: &Vector3<f32>| {
+        let cos = n.dot(v).abs();
+        2. * cos / (cos + (alpha * alpha + (1. - alpha * alpha) * cos * cos).sqrt())
+    };
+    g1(wo) * g1(wi)
+}
+
+// Reflection half vector, on the side of `n`
+fn half_vector(n: &Vector3<f32>, wo: &Vector3<f32>, wi: &Vector3<f32>) -> Vector3<f32> {
+    let m = (wo + wi).normalize();
+    if m.dot(n) < 0. {
+        -m
+    } else {
+        m
+    }
+}
+
+// Generalized half vector of reflection and refraction, on the side of `n`,
+// with the relative index of refraction seen from `wo` (1 for reflection).
+// None for degenerate configurations and back facing microfacets.
+fn dielectric_half_vector(
+    n: &Vector3<f32>,
+    wo: &Vector3<f32>,
+    wi: &Vector3<f32>,
+    ior: f32,
+) -> Option<(Vector3<f32>, f32)> {
+    let cos_o = n.dot(wo);
+    let cos_i = n.dot(wi);
+    let etap = if cos_o * cos_i > 0. {
+        1.
+    } else if cos_o > 0. {
+        ior
+    } else {
+        1. / ior
+    };
+    let m = (wi * etap + wo).try_normalize(f32::EPSILON)?;
+    let m = if m.dot(n) < 0. { -m } else { m };
+    if m.dot(wi) * cos_i < 0. || m.dot(wo) * cos_o < 0. {
+        return None;
+    }
+    Some((m, etap))
+}
+
+fn schlick(f0: &Vector3<f32>, cos_theta: f32) -> Vector3<f32> {
+    let t = (1. - cos_theta).clamp(0., 1.).powi(5);
+    f0 + (Vector3::new(1., 1., 1.) - f0) * t
+}
+
+// Unpolarized Fresnel reflectance, `cos_theta` is measured against the normal
+// pointing out of the medium of index `ior`
+fn fresnel_dielectric(cos_theta: f32, ior: f32) -> f32 {
+    let (mut cos_i, mut eta) = (cos_theta.clamp(-1., 1.), ior);
+    if cos_i < 0. {
+        eta = 1. / eta;
+        cos_i = -cos_i;
+    }
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).max(0.).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.
+}
+
+fn reflect(wo: &Vector3<f32>, m: &Vector3<f32>) -> Vector3<f32> {
+    2. * wo.dot(m) * m - wo
+}
+
+// `wo` leaves the surface on the side of `m`, `eta` is the index of the far
+// side over the index of the near side
+fn refract(wo: &Vector3<f32>, m: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_o = wo.dot(m);
+    let sin2_t = (1. - cos_o * cos_o).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-wo / eta + (cos_o / eta - cos_t) * m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Jittered grid over (cos theta, phi), uniform on the sphere
+    const GRID: (usize, usize) = (200, 400);
+    const SAMPLES: usize = 100_000;
+
+    fn stratified_sphere(rng: &mut Rng) -> impl Iterator<Item = Vector3<f32>> + '_ {
+        let (rows, cols) = GRID;
+        (0..rows * cols).map(move |k| {
+            let z = 1. - 2. * ((k / cols) as f32 + rng.next_f32()) / rows as f32;
+            let phi = 2. * PI * ((k % cols) as f32 + rng.next_f32()) / cols as f32;
+            let r = (1. - z * z).max(0.).sqrt();
+            Vector3::new(r * phi.cos(), r * phi.sin(), z)
+        })
+    }
+
+    fn materials() -> [Material; 3] {
+        [
+            Material::Conductor { roughness: 0.5 },
+            Material::Dielectric {
+                ior: 1.5,
+                roughness: 0.5,
+            },
+            Material::Dielectric {
+                ior: 1.33,
+                roughness: 0.7,
+            },
+        ]
+    }
+
+    fn directions() -> [Vector3<f32>; 3] {
+        [
+            Vector3::z(),
+            Vector3::new(0.6, 0., 0.8),
+            -Vector3::new(0., 0.6, 0.8),
+        ]
+    }
+
+    // Integrals of pdf and bsdf * |cos| over the sphere, then the mean
+    // `sample` weight and success rate. Sums are kept in f64, f32 drifts over
+    // this many terms.
+    fn estimates(material: &Material, wo: &Vector3<f32>) -> (f64, Vector3<f64>, Vector3<f64>, f64) {
+        let n = Vector3::z();
+        let color = Vector3::new(0.9, 0.6, 0.3);
+        let mut rng = Rng::new(1);
+
+        let (mut pdf, mut albedo) = (0., Vector3::zeros());
+        for wi in stratified_sphere(&mut rng) {
+            pdf += material.pdf(&n, wo, &wi) as f64;
+            albedo += (material.eval(&color, &n, wo, &wi) * n.dot(&wi).abs()).cast::<f64>();
+        }
+        let solid_angle = 4. * std::f64::consts::PI / (GRID.0 * GRID.1) as f64;
+
+        let (mut weight, mut sampled) = (Vector3::zeros(), 0);
+        for _ in 0..SAMPLES {
+            if let Some(s) = material.sample(&color, &n, wo, &mut rng) {
+                assert!(!s.specular);
+                assert!((s.pdf - material.pdf(&n, wo, &s.wi)).abs() <= 1e-3 * s.pdf);
+                weight += s.weight.cast::<f64>();
+                sampled += 1;
+            }
+        }
+        let count = SAMPLES as f64;
+        (
+            pdf * solid_angle,
+            albedo * solid_angle,
+            weight / count,
+            sampled as f64 / count,
+        )
+    }
+
+    #[test]
+    fn sampling_matches_pdf_and_eval() {
+        for wo in directions() {
+            for material in materials() {
+                let (pdf, albedo, weight, sampled) = estimates(&material, &wo);
+                // the pdf integrates to the probability of `sample` succeeding
+                assert!(
+                    (pdf - sampled).abs() < 0.02,
+                    "{material:?} {wo:?}: {pdf} vs {sampled}"
+                );
+                assert!(
+                    (albedo - weight).norm() < 0.02,
+                    "{material:?} {wo:?}: {albedo:?} vs {weight:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lambertian_albedo_is_its_color() {
+        let (pdf, albedo, weight, _) =
+            estimates(&Material::Lambertian, &Vector3::new(0.6, 0., 0.8));
+        let color = Vector3::new(0.9, 0.6, 0.3);
+        assert!((pdf - 1.).abs() < 1e-3);
+        assert!((albedo - color.cast::<f64>()).norm() < 1e-3);
+        assert!((weight - color.cast::<f64>()).norm() < 1e-4);
+    }
+
+    #[test]
+    fn mirror_is_a_delta() {
+        let (n, wo) = (Vector3::z(), Vector3::new(0.6, 0., 0.8));
+        let color = Vector3::new(0.9, 0.6, 0.3);
+        let s = Material::Mirror
+            .sample(&color, &n, &wo, &mut Rng::new(1))
+            .unwrap();
+        assert!(s.specular);
+        assert!((s.wi - Vector3::new(-0.6, 0., 0.8)).norm() < 1e-6);
+        assert_eq!(s.weight, color);
+        assert_eq!(
+            Material::Mirror.eval(&color, &n, &wo, &s.wi),
+            Vector3::zeros()
+        );
+        assert_eq!(Material::Mirror.pdf(&n, &wo, &s.wi), 0.);
+    }
+}
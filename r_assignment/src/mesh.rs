@@ -0,0 +1,131 @@
+#![allow(dead_code)]
+
+use crate::triangle::Triangle;
+use nalgebra::{Vector2, Vector3, Vector4};
+
+// Indexed triangle mesh, attribute vectors are either empty or one entry per
+// position. Colors use the same [0, 255] range as `Rasterizer::load_colors`.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Vec<Vector3<f32>>,
+    pub tex_coords: Vec<Vector2<f32>>,
+    pub tangents: Vec<Vector4<f32>>,
+    pub colors: Vec<Vector3<f32>>,
+    pub indices: Vec<Vector3<i32>>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vector3<f32>>, indices: Vec<Vector3<i32>>) -> Self {
+        Self {
+            positions,
+            indices,
+            ..Default::default()
+        }
+    }
+
+    // Per-vertex tangents in the spirit of MikkTSpace: per-face tangents from
+    // the UV gradients are accumulated with angle weights, orthogonalized
+    // against the normal and tagged with the bitangent handedness
+    pub fn generate_tangents(&mut self) -> Result<(), String> {
+        let n = self.positions.len();
+        if self.normals.len() != n || self.tex_coords.len() != n {
+            return Err("Tangents need a normal and tex coord per vertex".to_string());
+        }
+
+        let mut tan = vec![Vector3::<f32>::zeros(); n];
+        let mut bitan = vec![Vector3::<f32>::zeros(); n];
+        for face in &self.indices {
+            let i = [face[0] as usize, face[1] as usize, face[2] as usize];
+            if i.iter().any(|&k| k >= n) {
+                return Err("Invalid ind".to_string());
+            }
+            let p = i.map(|k| self.positions[k]);
+            let uv = i.map(|k| self.tex_coords[k]);
+
+            let e1 = p[1] - p[0];
+            let e2 = p[2] - p[0];
+            let duv1 = uv[1] - uv[0];
+            let duv2 = uv[2] - uv[0];
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let t = (e1 * duv2.y - e2 * duv1.y) / det;
+            let b = (e2 * duv1.x - e1 * duv2.x) / det;
+
+            for k in 0..3 {
+                let a = p[(k + 1) % 3] - p[k];
+                let c = p[(k + 2) % 3] - p[k];
+                let angle = match (a.try_normalize(0.), c.try_normalize(0.)) {
+                    (Some(a), Some(c)) => a.dot(&c).clamp(-1., 1.).acos(),
+                    _ => 0.,
+                };
+                tan[i[k]] += t * angle;
+                bitan[i[k]] += b * angle;
+            }
+        }
+
+        self.tangents = (0..n)
+            .map(|k| {
+                let normal = self.normals[k];
+                let t = (tan[k] - normal * normal.dot(&tan[k]))
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(|| any_perpendicular(&normal));
+                let w = if normal.cross(&t).dot(&bitan[k]) < 0. {
+                    -1.
+                } else {
+                    1.
+                };
+                Vector4::new(t.x, t.y, t.z, w)
+            })
+            .collect();
+        Ok(())
+    }
+
+    // Model space triangles for `Rasterizer::draw_triangles`, missing normals
+    // fall back to the face normal and missing colors to white
+    pub fn triangles(&self) -> Result<Vec<Triangle>, String> {
+        let n = self.positions.len();
+        let mut triangles = Vec::with_capacity(self.indices.len());
+        for face in &self.indices {
+            let mut t = Triangle::default();
+            for j in 0..3 {
+                let k = face[j] as usize;
+                if k >= n {
+                    return Err("Invalid ind".to_string());
+                }
+                t.set_vertex(j, self.positions[k])?;
+                if let Some(uv) = self.tex_coords.get(k) {
+                    t.set_tex_coord(j, uv.x, uv.y)?;
+                }
+                if let Some(tangent) = self.tangents.get(k) {
+                    t.set_tangent(j, *tangent)?;
+                }
+                let c = self
+                    .colors
+                    .get(k)
+                    .copied()
+                    .unwrap_or(Vector3::new(255., 255., 255.));
+                t.set_color(j, c[0], c[1], c[2])?;
+            }
+
+            let face_normal = (t.b() - t.a()).cross(&(t.c() - t.a())).normalize();
+            for j in 0..3 {
+                let normal = self.normals.get(face[j] as usize).copied();
+                t.set_normal(j, normal.unwrap_or(face_normal))?;
+            }
+            triangles.push(t);
+        }
+        Ok(triangles)
+    }
+}
+
+fn any_perpendicular(n: &Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x.abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    (axis - n * n.dot(&axis)).normalize()
+}
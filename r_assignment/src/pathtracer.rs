@@ -0,0 +1,291 @@
+#![allow(dead_code)]
+
+use crate::bvh::{Bvh, SplitMethod};
+use crate::material::Material;
+use crate::random::Rng;
+use crate::ray::Ray;
+use crate::triangle::Triangle;
+use nalgebra::{Matrix4, Vector3, Vector4};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Unbiased global illumination over triangle meshes in the manner of GAMES101
+// assignment 7. Emissive triangles are area lights, sampled explicitly at
+// every bounce.
+pub struct PathTracer {
+    bvh: Bvh,
+    // one entry per triangle
+    materials: Vec<Material>,
+    // one entry per triangle, zero for non-emitters
+    emission: Vec<Vector3<f32>>,
+    // emissive triangles with the cumulative area used to pick one
+    lights: Vec<usize>,
+    light_cdf: Vec<f32>,
+
+    pub samples_per_pixel: u32,
+    // Probability of continuing a path after each bounce
+    pub russian_roulette: f32,
+    pub tile_size: usize,
+    pub threads: usize,
+    // Radiance of rays leaving the scene
+    pub background: Vector3<f32>,
+    // Offset of secondary ray origins against self intersection
+    pub epsilon: f32,
+}
+
+// Point on an area light picked for next event estimation
+struct LightSample {
+    point: Vector3<f32>,
+    normal: Vector3<f32>,
+    emission: Vector3<f32>,
+    // with respect to area
+    pdf: f32,
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PathTracer {
+    pub fn new() -> Self {
+        Self {
+            bvh: Bvh::new(Vec::new(), SplitMethod::Sah),
+            materials: Vec::new(),
+            emission: Vec::new(),
+            lights: Vec::new(),
+            light_cdf: Vec::new(),
+            samples_per_pixel: 16,
+            russian_roulette: 0.8,
+            tile_size: 16,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            background: Vector3::zeros(),
+            epsilon: 1e-4,
+        }
+    }
+
+    // Adds model space triangles, their colors parameterize `material`.
+    // Triangles with non-zero `emission` (linear radiance, 1 is white) emit on
+    // the side their vertex normals face.
+    pub fn add(
+        &mut self,
+        triangles: &[Triangle],
+        model: &Matrix4<f32>,
+        material: Material,
+        emission: Vector3<f32>,
+    ) {
+        let normal_model = model
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+            .transpose();
+        let mut all = self.bvh.triangles().to_vec();
+        for t in triangles {
+            let mut world = t.clone();
+            for j in 0..3 {
+                world.set_vertex(j, (model * t.v()[j].push(1.)).xyz()).ok();
+                let n = (normal_model * t.normal()[j].push(0.)).xyz();
+                world.set_normal(j, n.normalize()).ok();
+            }
+            if emission.max() > 0. {
+                let total = self.light_cdf.last().copied().unwrap_or(0.);
+                self.lights.push(all.len());
+                self.light_cdf.push(total + area(&world));
+            }
+            all.push(world);
+            self.materials.push(material);
+            self.emission.push(emission);
+        }
+        self.bvh = Bvh::new(all, SplitMethod::Sah);
+    }
+
+    // Averages `samples_per_pixel` paths through every pixel, framed by the
+    // same matrices as the rasterizer. The result uses the
+    // `Rasterizer::framebuffer` layout with 255 meaning a radiance of 1 and
+    // is not clamped, tone mapping is left to the output stage.
+    pub fn render(
+        &self,
+        width: usize,
+        height: usize,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+    ) -> Vec<Vector3<f32>> {
+        self.render_pass(width, height, view, projection, self.samples_per_pixel, 0)
+    }
+
+    // Like `render` with `spp` paths per pixel, `seed` decorrelates passes
+    pub fn render_pass(
+        &self,
+        width: usize,
+        height: usize,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+        spp: u32,
+        seed: u64,
+    ) -> Vec<Vector3<f32>> {
+        let inv_vp = (projection * view)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |x: f32, y: f32, z: f32| {
+            let p = inv_vp * Vector4::new(x, y, z, 1.);
+            p.xyz() / p.w
+        };
+
+        let tile_size = self.tile_size.max(1);
+        let tiles_x = width.div_ceil(tile_size);
+        let tiles = tiles_x * height.div_ceil(tile_size);
+        let next_tile = AtomicUsize::new(0);
+
+        let mut frame_buf = vec![Vector3::zeros(); width * height];
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut pixels = Vec::new();
+                        loop {
+                            let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                            if tile >= tiles {
+                                break;
+                            }
+                            let mut rng = Rng::new(mix(seed, tile as u64));
+                            let x0 = tile % tiles_x * tile_size;
+                            let y0 = tile / tiles_x * tile_size;
+                            for y in y0..(y0 + tile_size).min(height) {
+                                for x in x0..(x0 + tile_size).min(width) {
+                                    let mut color = Vector3::zeros();
+                                    for _ in 0..spp {
+                                        // jittered inside the pixel
+                                        let sx = x as f32 + rng.next_f32();
+                                        let sy = y as f32 + rng.next_f32();
+                                        let ndc_x = sx / width as f32 * 2. - 1.;
+                                        let ndc_y = sy / height as f32 * 2. - 1.;
+                                        let near = unproject(ndc_x, ndc_y, -1.);
+                                        let far = unproject(ndc_x, ndc_y, 1.);
+                                        let ray = Ray::new(near, far - near);
+                                        color += self.cast_ray(&ray, &mut rng);
+                                    }
+                                    let ind = (height - y - 1) * width + x;
+                                    pixels.push((ind, color / spp.max(1) as f32 * 255.));
+                                }
+                            }
+                        }
+                        pixels
+                    })
+                })
+                .collect();
+            for worker in workers {
+                for (ind, color) in worker.join().expect("Path tracing thread panicked") {
+                    frame_buf[ind] = color;
+                }
+            }
+        });
+        frame_buf
+    }
+
+    // One path sample of the radiance along `ray`, linear with 1 as white
+    pub fn cast_ray(&self, ray: &Ray, rng: &mut Rng) -> Vector3<f32> {
+        let mut radiance = Vector3::zeros();
+        let mut throughput = Vector3::new(1., 1., 1.);
+        let mut ray = *ray;
+        // the previous bounce was a delta one, light sampling missed it
+        let mut specular = true;
+
+        loop {
+            let Some(hit) = self.bvh.intersect(&ray) else {
+                radiance += throughput.component_mul(&self.background);
+                break;
+            };
+
+            // emitters reached otherwise were already counted by light sampling
+            let emission = self.emission[hit.triangle];
+            if emission.max() > 0. {
+                if specular && ray.direction.dot(&hit.normal) < 0. {
+                    radiance += throughput.component_mul(&emission);
+                }
+                break;
+            }
+
+            let material = self.materials[hit.triangle];
+            let n = hit.normal;
+            let wo = -ray.direction;
+            // secondary rays start on the side they leave from
+            let offset = |dir: &Vector3<f32>| hit.point + n * (self.epsilon * n.dot(dir).signum());
+
+            // direct lighting from a point on an area light
+            if !material.is_specular() {
+                if let Some(light) = self.sample_light(rng) {
+                    let origin = offset(&(light.point - hit.point));
+                    let to_light = light.point - origin;
+                    let distance = to_light.norm();
+                    let l = to_light / distance;
+                    let cos_light = -light.normal.dot(&l);
+                    let f = material.eval(&hit.color, &n, &wo, &l);
+                    if cos_light > 0. && f.max() > 0. {
+                        let shadow_ray = Ray::new(origin, l);
+                        let blocked = self
+                            .bvh
+                            .intersect_range(&shadow_ray, 0., distance - 2. * self.epsilon)
+                            .is_some();
+                        if !blocked {
+                            let g = n.dot(&l).abs() * cos_light / (distance * distance);
+                            radiance += throughput.component_mul(&light.emission).component_mul(&f)
+                                * (g / light.pdf);
+                        }
+                    }
+                }
+            }
+
+            if rng.next_f32() >= self.russian_roulette {
+                break;
+            }
+            let Some(sample) = material.sample(&hit.color, &n, &wo, rng) else {
+                break;
+            };
+            throughput = throughput.component_mul(&sample.weight) / self.russian_roulette;
+            specular = sample.specular;
+            ray = Ray::new(offset(&sample.wi), sample.wi);
+        }
+        radiance
+    }
+
+    fn sample_light(&self, rng: &mut Rng) -> Option<LightSample> {
+        let total = *self.light_cdf.last()?;
+        let target = rng.next_f32() * total;
+        let k = self
+            .light_cdf
+            .partition_point(|&c| c <= target)
+            .min(self.lights.len() - 1);
+        let index = self.lights[k];
+        let t = &self.bvh.triangles()[index];
+
+        // uniform over the triangle
+        let r1 = rng.next_f32().sqrt();
+        let r2 = rng.next_f32();
+        let v = t.v();
+        let point = v[0] * (1. - r1) + v[1] * (r1 * (1. - r2)) + v[2] * (r1 * r2);
+        let mut normal = (v[1] - v[0]).cross(&(v[2] - v[0])).normalize();
+        if normal.dot(&t.normal()[0]) < 0. {
+            normal = -normal;
+        }
+        Some(LightSample {
+            point,
+            normal,
+            emission: self.emission[index],
+            pdf: 1. / total,
+        })
+    }
+}
+
+fn area(t: &Triangle) -> f32 {
+    let v = t.v();
+    (v[1] - v[0]).cross(&(v[2] - v[0])).norm() * 0.5
+}
+
+// Seed of the generator of one tile
+fn mix(seed: u64, tile: u64) -> u64 {
+    let mut z = seed
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        .wrapping_add(tile.wrapping_mul(0xBF58_476D_1CE4_E5B9));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
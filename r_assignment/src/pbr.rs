@@ -0,0 +1,301 @@
+#![allow(dead_code)]
+
+use crate::environment::EnvironmentMap;
+use crate::light::PointLight;
+use crate::shader::FragmentShaderPayload;
+use crate::texture::Texture;
+use crate::tonemap::srgb_to_linear;
+use nalgebra::{Matrix3, Matrix4, Vector3};
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+// glTF style metallic-roughness material. Every factor is multiplied by its
+// texture when one is set, base color is also multiplied by the vertex color.
+#[derive(Debug, Clone)]
+pub struct PbrMaterial {
+    // Linear RGB in [0, 1]
+    pub base_color: Vector3<f32>,
+    pub metallic: f32,
+    pub roughness: f32,
+    // Linear RGB, may exceed 1
+    pub emissive: Vector3<f32>,
+
+    // sRGB encoded
+    pub base_color_texture: Option<Rc<Texture>>,
+    // Linear, roughness in the green channel and metallic in the blue channel
+    pub metallic_roughness_texture: Option<Rc<Texture>>,
+    // sRGB encoded
+    pub emissive_texture: Option<Rc<Texture>>,
+}
+
+// Material inputs resolved at a shading point
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub base_color: Vector3<f32>,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vector3<f32>,
+}
+
+impl PbrMaterial {
+    pub fn new(base_color: Vector3<f32>, metallic: f32, roughness: f32) -> Self {
+        Self {
+            base_color,
+            metallic,
+            roughness,
+            ..Default::default()
+        }
+    }
+
+    pub fn sample(&self, payload: &FragmentShaderPayload) -> SurfaceSample {
+        let (u, v) = (payload.tex_coords.x, payload.tex_coords.y);
+        let texel =
+            |texture: &Option<Rc<Texture>>| texture.as_ref().map(|t| t.get_color(u, v) / 255.);
+
+        let mut base_color = self.base_color.component_mul(&payload.color);
+        if let Some(c) = texel(&self.base_color_texture) {
+            base_color = base_color.component_mul(&srgb_to_linear(&c));
+        }
+        let (mut metallic, mut roughness) = (self.metallic, self.roughness);
+        if let Some(c) = texel(&self.metallic_roughness_texture) {
+            roughness *= c.y;
+            metallic *= c.z;
+        }
+        let mut emissive = self.emissive;
+        if let Some(c) = texel(&self.emissive_texture) {
+            emissive = emissive.component_mul(&srgb_to_linear(&c));
+        }
+
+        SurfaceSample {
+            base_color,
+            metallic: metallic.clamp(0., 1.),
+            // perfectly smooth surfaces turn the GGX lobe into a delta
+            roughness: roughness.clamp(0.04, 1.),
+            emissive,
+        }
+    }
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Vector3::new(1., 1., 1.),
+            metallic: 1.,
+            roughness: 1.,
+            emissive: Vector3::zeros(),
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+// Cook-Torrance BRDF with GGX distribution, Smith-Schlick geometry and
+// Schlick Fresnel, times the cosine term. `l`, `v` and `n` are unit vectors.
+pub fn cook_torrance(
+    surface: &SurfaceSample,
+    n: &Vector3<f32>,
+    v: &Vector3<f32>,
+    l: &Vector3<f32>,
+) -> Vector3<f32> {
+    let n_dot_l = n.dot(l);
+    let n_dot_v = n.dot(v).max(1e-4);
+    if n_dot_l <= 0. {
+        return Vector3::zeros();
+    }
+    let h = (l + v).normalize();
+    let n_dot_h = n.dot(&h).max(0.);
+    let v_dot_h = v.dot(&h).max(0.);
+
+    let a = surface.roughness * surface.roughness;
+    let a2 = a * a;
+    let denom = n_dot_h * n_dot_h * (a2 - 1.) + 1.;
+    let d = a2 / (PI * denom * denom);
+
+    let k = (surface.roughness + 1.).powi(2) / 8.;
+    let g = n_dot_v / (n_dot_v * (1. - k) + k) * n_dot_l / (n_dot_l * (1. - k) + k);
+
+    let f0 = base_reflectance(surface);
+    let f = fresnel_schlick(&f0, v_dot_h);
+
+    let specular = f * (d * g / (4. * n_dot_v * n_dot_l));
+    let kd = (Vector3::new(1., 1., 1.) - f) * (1. - surface.metallic);
+    let diffuse = kd.component_mul(&surface.base_color) / PI;
+    (diffuse + specular) * n_dot_l
+}
+
+// Reflectance at normal incidence, 4% for dielectrics
+pub fn base_reflectance(surface: &SurfaceSample) -> Vector3<f32> {
+    let dielectric = Vector3::new(0.04, 0.04, 0.04);
+    dielectric.lerp(&surface.base_color, surface.metallic)
+}
+
+pub fn fresnel_schlick(f0: &Vector3<f32>, cos_theta: f32) -> Vector3<f32> {
+    let t = (1. - cos_theta).clamp(0., 1.).powi(5);
+    f0 + (Vector3::new(1., 1., 1.) - f0) * t
+}
+
+// Fresnel averaged over the lobe for ambient lighting
+pub fn fresnel_schlick_roughness(
+    f0: &Vector3<f32>,
+    cos_theta: f32,
+    roughness: f32,
+) -> Vector3<f32> {
+    let t = (1. - cos_theta).clamp(0., 1.).powi(5);
+    let max = Vector3::repeat(1. - roughness).sup(f0);
+    f0 + (max - f0) * t
+}
+
+// Analytic fit of the split-sum environment BRDF (Karis, "Physically Based
+// Shading on Mobile"), returns the scale and bias applied to F0
+pub fn env_brdf_approx(roughness: f32, n_dot_v: f32) -> (f32, f32) {
+    let c0 = [-1., -0.0275, -0.572, 0.022];
+    let c1 = [1., 0.0425, 1.04, -0.04];
+    let r: [f32; 4] = std::array::from_fn(|i| roughness * c0[i] + c1[i]);
+    let a004 = (r[0] * r[0]).min((-9.28 * n_dot_v).exp2()) * r[0] + r[1];
+    (-1.04 * a004 + r[2], 1.04 * a004 + r[3])
+}
+
+// Metallic-roughness shading of a rasterized fragment. Lights and the eye
+// are in view space, the eye sitting at the origin.
+pub struct PbrShader {
+    pub material: PbrMaterial,
+    pub lights: Vec<PointLight>,
+    // Uniform ambient radiance, replaced by image based lighting when an
+    // environment map is set
+    pub ambient: Vector3<f32>,
+    pub environment: Option<Rc<EnvironmentMap>>,
+    // Rotates view space directions into the world space of the environment
+    view_to_world: Matrix3<f32>,
+}
+
+impl PbrShader {
+    pub fn new(material: PbrMaterial, lights: Vec<PointLight>) -> Self {
+        Self {
+            material,
+            lights,
+            ambient: Vector3::new(0.03, 0.03, 0.03),
+            environment: None,
+            view_to_world: Matrix3::identity(),
+        }
+    }
+
+    pub fn with_environment(
+        mut self,
+        environment: Rc<EnvironmentMap>,
+        view: &Matrix4<f32>,
+    ) -> Self {
+        self.environment = Some(environment);
+        self.view_to_world = view.fixed_view::<3, 3>(0, 0).transpose();
+        self
+    }
+
+    fn ambient(&self, surface: &SurfaceSample, n: &Vector3<f32>, v: &Vector3<f32>) -> Vector3<f32> {
+        let Some(environment) = &self.environment else {
+            return self.ambient.component_mul(&surface.base_color);
+        };
+        let n_dot_v = n.dot(v).max(1e-4);
+        let r = 2. * n_dot_v * n - v;
+
+        let f0 = base_reflectance(surface);
+        let f = fresnel_schlick_roughness(&f0, n_dot_v, surface.roughness);
+        let kd = (Vector3::new(1., 1., 1.) - f) * (1. - surface.metallic);
+        let irradiance = environment.irradiance(&(self.view_to_world * n));
+        let diffuse = kd
+            .component_mul(&surface.base_color)
+            .component_mul(&irradiance)
+            / PI;
+
+        let (scale, bias) = env_brdf_approx(surface.roughness, n_dot_v);
+        let prefiltered = environment.specular(&(self.view_to_world * r), surface.roughness);
+        let specular = prefiltered.component_mul(&(f0 * scale + Vector3::repeat(bias)));
+        diffuse + specular
+    }
+
+    pub fn shade(&self, payload: &FragmentShaderPayload) -> Vector3<f32> {
+        let surface = self.material.sample(payload);
+        let n = payload.normal.normalize();
+        let v = (-payload.view_pos).normalize();
+
+        let mut radiance = surface.emissive + self.ambient(&surface, &n, &v);
+        for light in &self.lights {
+            let (l, irradiance) = light.incident(&payload.view_pos);
+            radiance += cook_torrance(&surface, &n, &v, &l).component_mul(&irradiance);
+        }
+        radiance * 255.
+    }
+}
+
+pub fn pbr_fragment_shader(shader: PbrShader) -> impl Fn(&FragmentShaderPayload) -> Vector3<f32> {
+    move |payload| shader.shade(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface(base_color: Vector3<f32>, metallic: f32, roughness: f32) -> SurfaceSample {
+        SurfaceSample {
+            base_color,
+            metallic,
+            roughness,
+            emissive: Vector3::zeros(),
+        }
+    }
+
+    #[test]
+    fn fresnel_schlick_known_values() {
+        let f0 = Vector3::new(0.04, 0.5, 1.);
+        assert!((fresnel_schlick(&f0, 1.) - f0).norm() < 1e-6);
+        assert!((fresnel_schlick(&f0, 0.) - Vector3::new(1., 1., 1.)).norm() < 1e-6);
+        // (1 - 0.5)^5 = 1/32 of the way to white
+        let expected = f0 + (Vector3::new(1., 1., 1.) - f0) / 32.;
+        assert!((fresnel_schlick(&f0, 0.5) - expected).norm() < 1e-6);
+    }
+
+    #[test]
+    fn cook_torrance_at_normal_incidence() {
+        // n = v = l: D = 1 / (pi a^2), G = 1 and F = F0, so the specular
+        // lobe is F0 / (4 pi a^2) with a = roughness^2
+        let n = Vector3::z();
+        let roughness: f32 = 0.5;
+        let peak = 1. / (4. * PI * roughness.powi(4));
+
+        let base_color = Vector3::new(1., 0.5, 0.25);
+        let metal = cook_torrance(&surface(base_color, 1., roughness), &n, &n, &n);
+        assert!((metal - base_color * peak).norm() < 1e-4);
+
+        // dielectrics reflect 4% and scatter the rest diffusely
+        let white = Vector3::new(1., 1., 1.);
+        let plastic = cook_torrance(&surface(white, 0., roughness), &n, &n, &n);
+        let expected = 0.04 * peak + 0.96 / PI;
+        assert!((plastic - white * expected).norm() < 1e-4);
+    }
+
+    #[test]
+    fn cook_torrance_is_zero_below_the_horizon() {
+        let n = Vector3::z();
+        let l = Vector3::new(1., 0., -0.1).normalize();
+        let brdf = cook_torrance(&surface(Vector3::new(1., 1., 1.), 0., 0.5), &n, &n, &l);
+        assert_eq!(brdf, Vector3::zeros());
+    }
+
+    #[test]
+    fn fragment_shader_applies_the_brdf_to_each_light() {
+        let material = PbrMaterial::new(Vector3::new(1., 1., 1.), 1., 0.5);
+        let light = PointLight::new(Vector3::zeros(), Vector3::new(1., 1., 1.));
+        let mut shader = PbrShader::new(material, vec![light]);
+        shader.ambient = Vector3::zeros();
+        let shade = pbr_fragment_shader(shader);
+
+        // facing the eye and the light one unit away
+        let payload = FragmentShaderPayload {
+            view_pos: Vector3::new(0., 0., -1.),
+            normal: Vector3::z(),
+            color: Vector3::new(1., 0.5, 0.25),
+            ..Default::default()
+        };
+        let peak = 1. / (4. * PI * 0.5f32.powi(4));
+        assert!((shade(&payload) - payload.color * peak * 255.).norm() < 1e-2);
+    }
+}
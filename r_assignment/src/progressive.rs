@@ -0,0 +1,119 @@
+#![allow(dead_code)]
+
+use nalgebra::{Matrix4, Vector3};
+
+// Running sum of frames in the `Rasterizer::framebuffer` layout, the average
+// converges as passes are added
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    sum: Vec<Vector3<f32>>,
+    samples: u32,
+    // projection * view the accumulated frames were rendered with
+    camera: Option<Matrix4<f32>>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sum: vec![Vector3::zeros(); width * height],
+            samples: 0,
+            camera: None,
+        }
+    }
+
+    // Drops everything accumulated, needed whenever the scene or camera moves
+    pub fn reset(&mut self) {
+        self.sum.fill(Vector3::zeros());
+        self.samples = 0;
+    }
+
+    // Call before adding the frames of a pass, resets when they are seen
+    // through another camera than the accumulated ones. Returns whether it did.
+    pub fn set_camera(&mut self, view: &Matrix4<f32>, projection: &Matrix4<f32>) -> bool {
+        let camera = projection * view;
+        if self.camera == Some(camera) {
+            return false;
+        }
+        self.camera = Some(camera);
+        self.reset();
+        true
+    }
+
+    // Adds a frame that already averages `samples` samples per pixel
+    pub fn add(&mut self, frame: &[Vector3<f32>], samples: u32) -> Result<(), String> {
+        if frame.len() != self.sum.len() {
+            return Err("Frame size does not match the accumulation buffer".to_string());
+        }
+        for (sum, pixel) in self.sum.iter_mut().zip(frame) {
+            *sum += pixel * samples as f32;
+        }
+        self.samples += samples;
+        Ok(())
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn average(&self) -> Vec<Vector3<f32>> {
+        let scale = 1. / self.samples.max(1) as f32;
+        self.sum.iter().map(|c| c * scale).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    fn accumulate(accumulator: &mut Accumulator, camera: &Camera, value: f32) -> bool {
+        let reset = accumulator.set_camera(&camera.view(), &camera.projection());
+        let frame = vec![Vector3::repeat(value); accumulator.width() * accumulator.height()];
+        accumulator.add(&frame, 1).unwrap();
+        reset
+    }
+
+    #[test]
+    fn averages_passes_of_the_same_camera() {
+        let camera = Camera::new(Vector3::new(0., 0., 5.), Vector3::zeros(), Vector3::y());
+        let mut accumulator = Accumulator::new(4, 3);
+        assert!(accumulate(&mut accumulator, &camera, 1.));
+        assert!(!accumulate(&mut accumulator, &camera, 3.));
+        assert_eq!(accumulator.samples(), 2);
+        assert_eq!(accumulator.average()[0], Vector3::repeat(2.));
+    }
+
+    #[test]
+    fn resets_when_the_camera_moves() {
+        let mut camera = Camera::new(Vector3::new(0., 0., 5.), Vector3::zeros(), Vector3::y());
+        let mut accumulator = Accumulator::new(4, 3);
+        accumulate(&mut accumulator, &camera, 1.);
+        accumulate(&mut accumulator, &camera, 1.);
+
+        // orbit with the left button, then pan with the right one
+        camera.mouse_move(100, 100, false, false, 300.);
+        camera.mouse_move(120, 100, true, false, 300.);
+        assert!(accumulate(&mut accumulator, &camera, 5.));
+        assert_eq!(accumulator.samples(), 1);
+        assert_eq!(accumulator.average()[0], Vector3::repeat(5.));
+
+        camera.mouse_move(120, 130, false, true, 300.);
+        assert!(accumulate(&mut accumulator, &camera, 7.));
+        assert_eq!(accumulator.average()[0], Vector3::repeat(7.));
+
+        camera.projection = camera.projection.next();
+        assert!(accumulate(&mut accumulator, &camera, 9.));
+        assert_eq!(accumulator.samples(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+
+use nalgebra::{Matrix3, Matrix4, Vector3};
+use std::ops::Mul;
+
+// Above this cosine slerp falls back to a normalized lerp, the sine in its
+// denominator is too small to divide by
+const SLERP_LINEAR_COS: f32 = 0.9995;
+
+// w + xi + yj + zk, rotations are kept at unit length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f32,
+    pub v: Vector3<f32>,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Self {
+            w,
+            v: Vector3::new(x, y, z),
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1., 0., 0., 0.)
+    }
+
+    // Same convention as `transform::rotation`, the angle is in degrees
+    pub fn from_axis_angle(axis: &Vector3<f32>, angle: f32) -> Self {
+        let (sin, cos) = (angle.to_radians() / 2.).sin_cos();
+        Self {
+            w: cos,
+            v: axis.normalize() * sin,
+        }
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let n = self.norm();
+        if n <= f32::EPSILON {
+            return Self::identity();
+        }
+        Self {
+            w: self.w / n,
+            v: self.v / n,
+        }
+    }
+
+    // The inverse rotation for unit quaternions
+    pub fn conjugate(&self) -> Self {
+        Self {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    // Axis and angle in degrees, the axis is x for the identity
+    pub fn axis_angle(&self) -> (Vector3<f32>, f32) {
+        let q = self.normalize();
+        let angle = 2. * q.w.clamp(-1., 1.).acos();
+        let axis = q.v.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::x);
+        (axis, angle.to_degrees())
+    }
+
+    pub fn rotate(&self, p: &Vector3<f32>) -> Vector3<f32> {
+        // v' = p + 2w (v x p) + 2 v x (v x p)
+        let t = 2. * self.v.cross(p);
+        p + self.w * t + self.v.cross(&t)
+    }
+
+    pub fn to_rotation_matrix(self) -> Matrix3<f32> {
+        let q = self.normalize();
+        let (w, x, y, z) = (q.w, q.v.x, q.v.y, q.v.z);
+        Matrix3::new(
+            1. - 2. * (y * y + z * z),
+            2. * (x * y - w * z),
+            2. * (x * z + w * y),
+            2. * (x * y + w * z),
+            1. - 2. * (x * x + z * z),
+            2. * (y * z - w * x),
+            2. * (x * z - w * y),
+            2. * (y * z + w * x),
+            1. - 2. * (x * x + y * y),
+        )
+    }
+
+    pub fn to_matrix(self) -> Matrix4<f32> {
+        self.to_rotation_matrix().to_homogeneous()
+    }
+
+    // Constant angular speed from `self` at t = 0 to `other` at t = 1, along
+    // the shorter of the two arcs
+    pub fn slerp(&self, other: &Self, t: f32) -> Self {
+        let (a, mut b) = (self.normalize(), other.normalize());
+        let mut cos = a.dot(&b);
+        // q and -q are the same rotation, pick the closer one
+        if cos < 0. {
+            b = Self { w: -b.w, v: -b.v };
+            cos = -cos;
+        }
+        let (wa, wb) = if cos > SLERP_LINEAR_COS {
+            (1. - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1. - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Self {
+            w: wa * a.w + wb * b.w,
+            v: a.v * wa + b.v * wb,
+        }
+        .normalize()
+    }
+}
+
+// Hamilton product, `a * b` rotates by b first and then by a
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * rhs.w - self.v.dot(&rhs.v),
+            v: rhs.v * self.w + self.v * rhs.w + self.v.cross(&rhs.v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // q and -q are the same rotation
+    fn same_rotation(a: &Quaternion, b: &Quaternion) -> bool {
+        (a.dot(b).abs() - 1.).abs() < 1e-5
+    }
+
+    fn pairs() -> Vec<(Quaternion, Quaternion)> {
+        let a = Quaternion::from_axis_angle(&Vector3::new(1., 2., 3.), 40.);
+        vec![
+            (Quaternion::identity(), a),
+            (a, Quaternion::from_axis_angle(&Vector3::y(), -150.)),
+            // the longer arc, slerp has to flip to the other hemisphere
+            (
+                a,
+                Quaternion::from_axis_angle(&Vector3::new(-1., 0., 2.), 300.),
+            ),
+            // close enough for the linear fallback
+            (a, a * Quaternion::from_axis_angle(&Vector3::x(), 0.5)),
+        ]
+    }
+
+    #[test]
+    fn slerp_hits_its_endpoints() {
+        for (a, b) in pairs() {
+            assert!(same_rotation(&a.slerp(&b, 0.), &a));
+            assert!(same_rotation(&a.slerp(&b, 1.), &b));
+        }
+    }
+
+    #[test]
+    fn slerp_stays_unit_length_at_constant_speed() {
+        for (a, b) in pairs() {
+            let total = a.slerp(&b, 1.).dot(&a).abs().min(1.).acos();
+            for i in 0..=20 {
+                let t = i as f32 / 20.;
+                let q = a.slerp(&b, t);
+                assert!((q.norm() - 1.).abs() < 1e-5);
+                let covered = q.dot(&a).abs().min(1.).acos();
+                assert!((covered - t * total).abs() < 2e-3, "{covered} at {t}");
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_matches_rotate() {
+        let q = Quaternion::from_axis_angle(&Vector3::new(1., -2., 0.5), 73.);
+        let p = Vector3::new(0.3, -1.2, 2.);
+        assert!((q.to_rotation_matrix() * p - q.rotate(&p)).norm() < 1e-5);
+        let (axis, angle) = q.axis_angle();
+        assert!((axis - Vector3::new(1., -2., 0.5).normalize()).norm() < 1e-5);
+        assert!((angle - 73.).abs() < 1e-3);
+        // b first, then a
+        let b = Quaternion::from_axis_angle(&Vector3::z(), 90.);
+        let ab = (q * b).rotate(&p);
+        assert!((ab - q.rotate(&b.rotate(&p))).norm() < 1e-5);
+    }
+}
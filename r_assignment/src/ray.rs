@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+
+use crate::triangle::Triangle;
+use nalgebra::{Vector2, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    // unit length
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+// Closest intersection of a ray with a triangle list, attributes are
+// interpolated with the barycentrics of the hit point
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub t: f32,
+    // index of the triangle that was hit
+    pub triangle: usize,
+    // weights of the three vertices
+    pub barycentric: Vector3<f32>,
+    pub point: Vector3<f32>,
+    // interpolated vertex normal, not flipped towards the ray
+    pub normal: Vector3<f32>,
+    // color in [0, 1] like `Triangle::color`
+    pub color: Vector3<f32>,
+    pub tex_coords: Vector2<f32>,
+}
+
+impl Hit {
+    pub fn new(ray: &Ray, t: f32, index: usize, triangle: &Triangle, b1: f32, b2: f32) -> Self {
+        let barycentric = Vector3::new(1. - b1 - b2, b1, b2);
+        let normal = triangle.normal()[0] * barycentric.x
+            + triangle.normal()[1] * barycentric.y
+            + triangle.normal()[2] * barycentric.z;
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or_else(|| {
+            let v = triangle.v();
+            (v[1] - v[0]).cross(&(v[2] - v[0])).normalize()
+        });
+        Self {
+            t,
+            triangle: index,
+            barycentric,
+            point: ray.at(t),
+            normal,
+            color: triangle.color()[0] * barycentric.x
+                + triangle.color()[1] * barycentric.y
+                + triangle.color()[2] * barycentric.z,
+            tex_coords: triangle.tex_coords()[0] * barycentric.x
+                + triangle.tex_coords()[1] * barycentric.y
+                + triangle.tex_coords()[2] * barycentric.z,
+        }
+    }
+}
+
+// Möller–Trumbore, returns the ray parameter and the barycentrics of the
+// second and third vertex. Both sides of the triangle are hit.
+pub fn intersect_triangle(ray: &Ray, v: &[Vector3<f32>; 3]) -> Option<(f32, f32, f32)> {
+    let e1 = v[1] - v[0];
+    let e2 = v[2] - v[0];
+    let s1 = ray.direction.cross(&e2);
+    let det = s1.dot(&e1);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv_det = 1. / det;
+    let s = ray.origin - v[0];
+    let b1 = s1.dot(&s) * inv_det;
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+    let s2 = s.cross(&e1);
+    let b2 = s2.dot(&ray.direction) * inv_det;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+    let t = s2.dot(&e2) * inv_det;
+    if t <= 0. {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// Closest hit over all triangles with t in (t_min, t_max)
+pub fn intersect_all(ray: &Ray, triangles: &[Triangle], t_min: f32, t_max: f32) -> Option<Hit> {
+    let mut closest: Option<(f32, usize, f32, f32)> = None;
+    for (i, triangle) in triangles.iter().enumerate() {
+        if let Some((t, b1, b2)) = intersect_triangle(ray, triangle.v()) {
+            if t > t_min && t < closest.map_or(t_max, |c| c.0) {
+                closest = Some((t, i, b1, b2));
+            }
+        }
+    }
+    closest.map(|(t, i, b1, b2)| Hit::new(ray, t, i, &triangles[i], b1, b2))
+}
@@ -0,0 +1,224 @@
+#![allow(dead_code)]
+
+use crate::bvh::{Bvh, SplitMethod};
+use crate::light::{blinn_phong, Light, PhongMaterial};
+use crate::ray::{Hit, Ray};
+use crate::triangle::Triangle;
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+// Surface kinds of GAMES101 assignment 5
+#[derive(Debug, Clone, Copy)]
+pub enum WhittedMaterial {
+    // Blinn-Phong with the triangle colors as kd, the only kind lights and
+    // shadows act on
+    DiffuseAndGlossy(PhongMaterial),
+    // Perfect mirror tinted by the triangle colors
+    Reflection,
+    // Glass, reflection and refraction weighted by the Fresnel term
+    ReflectionAndRefraction { ior: f32 },
+}
+
+// World space triangles traced with recursive reflection and refraction and
+// hard shadows from shadow rays
+pub struct Scene {
+    bvh: Bvh,
+    // one entry per triangle
+    materials: Vec<WhittedMaterial>,
+
+    pub ambient_light: Vector3<f32>,
+    // Radiance of rays leaving the scene, [0, 1] scale
+    pub background: Vector3<f32>,
+    pub max_depth: u32,
+    // Offset of secondary ray origins against self intersection
+    pub epsilon: f32,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            bvh: Bvh::new(Vec::new(), SplitMethod::Sah),
+            materials: Vec::new(),
+            ambient_light: Vector3::new(10., 10., 10.),
+            background: Vector3::zeros(),
+            max_depth: 5,
+            epsilon: 1e-4,
+        }
+    }
+
+    // Adds model space triangles as given to `Rasterizer::draw_triangles`, the
+    // BVH is rebuilt so prefer adding whole meshes at once
+    pub fn add(&mut self, triangles: &[Triangle], model: &Matrix4<f32>, material: WhittedMaterial) {
+        let mut all = self.bvh.triangles().to_vec();
+        let normal_model = model
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+            .transpose();
+        for t in triangles {
+            let mut world = t.clone();
+            for j in 0..3 {
+                world.set_vertex(j, (model * t.v()[j].push(1.)).xyz()).ok();
+                let n = (normal_model * t.normal()[j].push(0.)).xyz();
+                world.set_normal(j, n.normalize()).ok();
+            }
+            all.push(world);
+            self.materials.push(material);
+        }
+        self.bvh = Bvh::new(all, SplitMethod::Sah);
+    }
+
+    pub fn triangles(&self) -> &[Triangle] {
+        self.bvh.triangles()
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.bvh.intersect(ray)
+    }
+
+    // Radiance along `ray` in [0, 1] scale, lights are in world space
+    pub fn cast_ray(&self, ray: &Ray, lights: &[Light], depth: u32) -> Vector3<f32> {
+        if depth > self.max_depth {
+            return Vector3::zeros();
+        }
+        let Some(hit) = self.intersect(ray) else {
+            return self.background;
+        };
+
+        // shade the side the ray arrives at
+        let outside = ray.direction.dot(&hit.normal) < 0.;
+        let n = if outside { hit.normal } else { -hit.normal };
+        let offset = n * self.epsilon;
+
+        match self.materials[hit.triangle] {
+            WhittedMaterial::DiffuseAndGlossy(material) => {
+                let eye_pos = ray.origin;
+                let shadow_origin = hit.point + offset;
+                let mut color = material.ka.component_mul(&self.ambient_light);
+                for light in lights {
+                    let (l, irradiance) = light.incident(&hit.point);
+                    if n.dot(&l) <= 0. {
+                        continue;
+                    }
+                    let shadow_ray = Ray::new(shadow_origin, l);
+                    let distance = light.distance(&shadow_origin);
+                    if self
+                        .bvh
+                        .intersect_range(&shadow_ray, 0., distance)
+                        .is_some()
+                    {
+                        continue;
+                    }
+                    color += blinn_phong(
+                        &l,
+                        &irradiance,
+                        &hit.point,
+                        &n,
+                        &eye_pos,
+                        &hit.color,
+                        &material,
+                    );
+                }
+                color
+            }
+            WhittedMaterial::Reflection => {
+                let r = reflect(&ray.direction, &n);
+                let reflected = self.cast_ray(&Ray::new(hit.point + offset, r), lights, depth + 1);
+                reflected.component_mul(&hit.color)
+            }
+            WhittedMaterial::ReflectionAndRefraction { ior } => {
+                let kr = fresnel(&ray.direction, &hit.normal, ior);
+                let r = reflect(&ray.direction, &n);
+                let mut color =
+                    self.cast_ray(&Ray::new(hit.point + offset, r), lights, depth + 1) * kr;
+                if kr < 1. {
+                    if let Some(t) = refract(&ray.direction, &hit.normal, ior) {
+                        let refracted =
+                            self.cast_ray(&Ray::new(hit.point - offset, t), lights, depth + 1);
+                        color += refracted * (1. - kr);
+                    }
+                }
+                color
+            }
+        }
+    }
+
+    // One ray through every pixel center, framed by the same matrices as the
+    // rasterizer. The result uses the `Rasterizer::framebuffer` layout and
+    // its convention of 255 meaning white.
+    pub fn render(
+        &self,
+        lights: &[Light],
+        width: usize,
+        height: usize,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+    ) -> Vec<Vector3<f32>> {
+        let inv_vp = (projection * view)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |x: f32, y: f32, z: f32| {
+            let p = inv_vp * Vector4::new(x, y, z, 1.);
+            p.xyz() / p.w
+        };
+
+        let mut frame_buf = vec![Vector3::zeros(); width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let ndc_x = (x as f32 + 0.5) / width as f32 * 2. - 1.;
+                let ndc_y = (y as f32 + 0.5) / height as f32 * 2. - 1.;
+                // from the near plane so nothing clipped by the rasterizer shows up
+                let near = unproject(ndc_x, ndc_y, -1.);
+                let far = unproject(ndc_x, ndc_y, 1.);
+                let ray = Ray::new(near, far - near);
+                frame_buf[(height - y - 1) * width + x] = self.cast_ray(&ray, lights, 0) * 255.;
+            }
+        }
+        frame_buf
+    }
+}
+
+// `i` points towards the surface
+pub fn reflect(i: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
+    i - 2. * i.dot(n) * n
+}
+
+// Snell's law, None on total internal reflection. `n` is the outward normal,
+// rays arriving from the inside leave the medium of index `ior`.
+pub fn refract(i: &Vector3<f32>, n: &Vector3<f32>, ior: f32) -> Option<Vector3<f32>> {
+    let mut cos_i = i.dot(n).clamp(-1., 1.);
+    let (mut eta_i, mut eta_t) = (1., ior);
+    let mut n = *n;
+    if cos_i < 0. {
+        cos_i = -cos_i;
+    } else {
+        std::mem::swap(&mut eta_i, &mut eta_t);
+        n = -n;
+    }
+    let eta = eta_i / eta_t;
+    let k = 1. - eta * eta * (1. - cos_i * cos_i);
+    if k < 0. {
+        None
+    } else {
+        Some(eta * i + (eta * cos_i - k.sqrt()) * n)
+    }
+}
+
+// Fraction of light reflected by a dielectric interface (unpolarized)
+pub fn fresnel(i: &Vector3<f32>, n: &Vector3<f32>, ior: f32) -> f32 {
+    let cos_i = i.dot(n).clamp(-1., 1.);
+    let (eta_i, eta_t) = if cos_i > 0. { (ior, 1.) } else { (1., ior) };
+    let sin_t = eta_i / eta_t * (1. - cos_i * cos_i).max(0.).sqrt();
+    if sin_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin_t * sin_t).max(0.).sqrt();
+    let cos_i = cos_i.abs();
+    let rs = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let rp = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    (rs * rs + rp * rp) / 2.
+}
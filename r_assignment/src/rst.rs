@@ -3,6 +3,13 @@
 #![allow(dead_code)]
 #![allow(unreachable_patterns)]
 
+use crate::environment::EnvironmentMap;
+use crate::gbuffer::GBuffer;
+use crate::light::{Light, PhongMaterial};
+use crate::lod::LodGroup;
+use crate::shader::{FragmentShader, FragmentShaderPayload};
+use crate::ssao::Ssao;
+use crate::texture::Texture;
 use crate::triangle::Triangle;
 use bitflags::bitflags;
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
@@ -97,6 +104,22 @@ pub enum AntiAliasing {
     Grid2x2,
 }
 
+// How vertex attributes are turned into per-pixel colors
+pub enum ShadingInterpolation {
+    // Color and normal of the provoking (first) vertex for the whole triangle
+    Flat,
+    // Shade at the vertices, interpolate the resulting colors
+    Gouraud,
+    // Interpolate attributes, shade every pixel
+    Phong,
+}
+
+pub enum RenderMode {
+    Forward,
+    // Geometry goes to the G-buffer, frame buffer is filled by `shade_deferred`
+    Deferred,
+}
+
 fn compute_barycentric_2d(x: f32, y: f32, v: &[Vector3<f32>; 3]) -> (f32, f32, f32) {
     let c1 = (x * (v[1].y - v[2].y) + (v[2].x - v[1].x) * y + v[1].x * v[2].y - v[2].x * v[1].y)
         / (v[0].x * (v[1].y - v[2].y) + (v[2].x - v[1].x) * v[0].y + v[1].x * v[2].y
@@ -110,6 +133,127 @@ fn compute_barycentric_2d(x: f32, y: f32, v: &[Vector3<f32>; 3]) -> (f32, f32, f
     (c1, c2, c3)
 }
 
+// Splits off the part of a model space triangle in front of the near plane
+// (clip space z >= -w), None when nothing needs to be cut away
+fn clip_near(t: &Triangle, mvp: &Matrix4<f32>) -> Option<Vec<Triangle>> {
+    let d = t.v().map(|p| {
+        let clip = mvp * p.push(1.);
+        clip.z + clip.w
+    });
+    if d.iter().all(|&d| d >= 0.) {
+        return None;
+    }
+    let corners = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    let mut polygon: Vec<[f32; 3]> = Vec::with_capacity(4);
+    for i in 0..3 {
+        let j = (i + 1) % 3;
+        if d[i] >= 0. {
+            polygon.push(corners[i]);
+        }
+        if (d[i] >= 0.) != (d[j] >= 0.) {
+            let s = d[i] / (d[i] - d[j]);
+            polygon.push(std::array::from_fn(|k| {
+                corners[i][k] * (1. - s) + corners[j][k] * s
+            }));
+        }
+    }
+    Some(
+        (1..polygon.len().saturating_sub(1))
+            .map(|k| t.sub_triangle(&[polygon[0], polygon[k], polygon[k + 1]]))
+            .collect(),
+    )
+}
+
+// Liang-Barsky clipping of a screen space segment to the 0..width, 0..height
+// rectangle, z is interpolated along with x and y
+fn clip_line(
+    begin: &Vector3<f32>,
+    end: &Vector3<f32>,
+    width: f32,
+    height: f32,
+) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let d = end - begin;
+    let (mut t0, mut t1) = (0f32, 1f32);
+    for (p, q) in [
+        (-d.x, begin.x),
+        (d.x, width - begin.x),
+        (-d.y, begin.y),
+        (d.y, height - begin.y),
+    ] {
+        if p == 0. {
+            if q < 0. {
+                return None;
+            }
+        } else if p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then(|| (begin + d * t0, begin + d * t1))
+}
+
+// Maps NDC z to the range stored in the depth buffer
+pub fn viewport_depth(z: f32) -> f32 {
+    let f1 = (100. - 0.1) / 2.0;
+    let f2 = (100. + 0.1) / 2.0;
+    z * f1 + f2
+}
+
+// Geometric normal of the view space triangle, facing like `reference`
+fn face_normal(view_pos: &[Vector3<f32>; 3], reference: &Vector3<f32>) -> Vector3<f32> {
+    let n = (view_pos[1] - view_pos[0])
+        .cross(&(view_pos[2] - view_pos[0]))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or(*reference);
+    if n.dot(reference) < 0. {
+        -n
+    } else {
+        n
+    }
+}
+
+fn distance_to_segment(p: &Vector2<f32>, a: &Vector2<f32>, b: &Vector2<f32>) -> f32 {
+    let ab = b - a;
+    let len2 = ab.norm_squared();
+    let t = if len2 > 0. {
+        ((p - a).dot(&ab) / len2).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (a + ab * t - p).norm()
+}
+
+fn interpolate(
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    vert: &[Vector3<f32>; 3],
+    weight: f32,
+) -> Vector3<f32> {
+    (alpha * vert[0] + beta * vert[1] + gamma * vert[2]) / weight
+}
+
+fn interpolate_vec4(
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    vert: &[Vector4<f32>; 3],
+    weight: f32,
+) -> Vector4<f32> {
+    (alpha * vert[0] + beta * vert[1] + gamma * vert[2]) / weight
+}
+
+fn interpolate_vec2(
+    alpha: f32,
+    beta: f32,
+    gamma: f32,
+    vert: &[Vector2<f32>; 3],
+    weight: f32,
+) -> Vector2<f32> {
+    (alpha * vert[0] + beta * vert[1] + gamma * vert[2]) / weight
+}
+
 pub struct Rasterizer {
     width: usize,
     height: usize,
@@ -128,6 +272,18 @@ pub struct Rasterizer {
 
     next_id: usize,
     antialiasing: AntiAliasing,
+
+    interpolation: ShadingInterpolation,
+    perspective_correct: bool,
+    render_mode: RenderMode,
+    gbuffer: GBuffer,
+    materials: Vec<PhongMaterial>,
+    material_id: usize,
+    ambient_light: Vector3<f32>,
+    ssao: Option<Ssao>,
+
+    texture: Option<Texture>,
+    fragment_shader: Option<FragmentShader>,
 }
 
 impl Rasterizer {
@@ -151,6 +307,16 @@ impl Rasterizer {
             projection: Matrix4::identity(),
             next_id: 0,
             antialiasing: antialising,
+            interpolation: ShadingInterpolation::Phong,
+            perspective_correct: true,
+            render_mode: RenderMode::Forward,
+            gbuffer: GBuffer::new(width, height),
+            materials: vec![PhongMaterial::default()],
+            material_id: 0,
+            ambient_light: Vector3::new(10., 10., 10.),
+            ssao: None,
+            texture: None,
+            fragment_shader: None,
         }
     }
 
@@ -164,6 +330,7 @@ impl Rasterizer {
                     *sample = Vector3::default();
                 }
             }
+            self.gbuffer.clear();
         }
         if buffers.contains(Buffers::Depth) {
             for depth in &mut self.depth_buf {
@@ -186,9 +353,61 @@ impl Rasterizer {
         self.projection = projection;
     }
 
+    // Gouraud and Phong only differ when a fragment shader is set, unshaded
+    // triangles interpolate their vertex colors either way
+    pub fn set_interpolation(&mut self, interpolation: ShadingInterpolation) {
+        self.interpolation = interpolation;
+    }
+
+    // false interpolates attributes linearly in screen space, which shows the
+    // texture swimming and color skew perspective correction removes
+    pub fn set_perspective_correct(&mut self, perspective_correct: bool) {
+        self.perspective_correct = perspective_correct;
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    // Registers a material for the deferred lighting pass, returns its id
+    pub fn add_material(&mut self, material: PhongMaterial) -> usize {
+        self.materials.push(material);
+        self.materials.len() - 1
+    }
+
+    // Material id written to the G-buffer by subsequent draws
+    pub fn set_material_id(&mut self, id: usize) {
+        self.material_id = id;
+    }
+
+    pub fn set_ambient_light(&mut self, intensity: Vector3<f32>) {
+        self.ambient_light = intensity;
+    }
+
+    pub fn set_texture(&mut self, texture: Texture) {
+        self.texture = Some(texture);
+    }
+
+    // Forward mode runs the shader once per covered pixel
+    pub fn set_fragment_shader(
+        &mut self,
+        shader: impl Fn(&FragmentShaderPayload) -> Vector3<f32> + 'static,
+    ) {
+        self.fragment_shader = Some(Box::new(shader));
+    }
+
+    // Ambient occlusion applied by the deferred lighting pass, None disables it
+    pub fn set_ssao(&mut self, ssao: Option<Ssao>) {
+        self.ssao = ssao;
+    }
+
     pub fn set_pixel(&mut self, point: &Vector3<f32>, samples_ind: usize, color: &Vector3<f32>) {
         // old index: auto ind = point.y() + point.x() * width;
-        if point.x as usize >= self.width || point.y as usize >= self.height {
+        if point.x < 0.
+            || point.y < 0.
+            || point.x as usize >= self.width
+            || point.y as usize >= self.height
+        {
             return;
         }
 
@@ -217,6 +436,18 @@ impl Rasterizer {
         ColBuf::new(id)
     }
 
+    pub fn positions(&self, pos_buffer: &PosBuf) -> Option<&Vec<Vector3<f32>>> {
+        self.pos_buf.get(pos_buffer.pos_id())
+    }
+
+    pub fn indices(&self, ind_buffer: &IndBuf) -> Option<&Vec<Vector3<i32>>> {
+        self.ind_buf.get(ind_buffer.ind_id())
+    }
+
+    pub fn colors(&self, col_buffer: &ColBuf) -> Option<&Vec<Vector3<f32>>> {
+        self.col_buf.get(col_buffer.col_id())
+    }
+
     pub fn draw(
         &mut self,
         pos_buffer: &PosBuf,
@@ -227,81 +458,236 @@ impl Rasterizer {
         // Implement triangle rasterization here
         match primitive {
             Primitive::Triangle => {
-                let buf = self
-                    .pos_buf
-                    .get(pos_buffer.pos_id())
-                    .ok_or("Invalid pos buffer id")?
-                    .clone();
-
-                let ind = self
-                    .ind_buf
-                    .get(ind_buffer.ind_id())
-                    .ok_or("Invalid ind buffer id")?
-                    .clone();
-
-                let col = self
-                    .col_buf
-                    .get(col_buffer.col_id())
-                    .ok_or("Invlid color buffer id")?
-                    .clone();
-
-                let f1 = (100. - 0.1) / 2.0;
-                let f2 = (100. + 0.1) / 2.0;
-
-                let mvp = self.projection * self.view * self.model;
-                for i in ind {
-                    let mut v = vec![
-                        mvp * Vector4::new(
-                            buf[i[0] as usize][0],
-                            buf[i[0] as usize][1],
-                            buf[i[0] as usize][2],
-                            1.,
-                        ),
-                        mvp * Vector4::new(
-                            buf[i[1] as usize][0],
-                            buf[i[1] as usize][1],
-                            buf[i[1] as usize][2],
-                            1.,
-                        ),
-                        mvp * Vector4::new(
-                            buf[i[2] as usize][0],
-                            buf[i[2] as usize][1],
-                            buf[i[2] as usize][2],
-                            1.,
-                        ),
-                    ];
-
-                    for vec in &mut v {
-                        *vec /= vec[3];
-                    }
+                let triangles = self.triangles(pos_buffer, ind_buffer, col_buffer)?;
+                self.draw_triangles(&triangles);
+                Ok(())
+            }
+            _ => Err("Not supported primitive".to_string()),
+        }
+    }
 
-                    for vert in &mut v {
-                        vert.x = 0.5 * self.width as f32 * (vert.x + 1.);
+    // Assembles the model space triangles described by loaded buffers
+    pub fn triangles(
+        &self,
+        pos_buffer: &PosBuf,
+        ind_buffer: &IndBuf,
+        col_buffer: &ColBuf,
+    ) -> Result<Vec<Triangle>, String> {
+        let buf = self
+            .pos_buf
+            .get(pos_buffer.pos_id())
+            .ok_or("Invalid pos buffer id")?;
 
-                        vert.y = 0.5 * self.height as f32 * (vert.y + 1.);
-                        vert.z = vert.z * f1 + f2;
-                    }
+        let ind = self
+            .ind_buf
+            .get(ind_buffer.ind_id())
+            .ok_or("Invalid ind buffer id")?;
+
+        let col = self
+            .col_buf
+            .get(col_buffer.col_id())
+            .ok_or("Invlid color buffer id")?;
+
+        let mut triangles = Vec::with_capacity(ind.len());
+        for i in ind {
+            let mut t = Triangle::default();
+            for j in 0..3 {
+                t.set_vertex(j, buf[i[j] as usize])?;
+                let c = col[i[j] as usize];
+                t.set_color(j, c[0], c[1], c[2]).ok();
+            }
+            // face normal, buffers carry no per-vertex normals
+            let n = (t.b() - t.a()).cross(&(t.c() - t.a())).normalize();
+            for j in 0..3 {
+                t.set_normal(j, n)?;
+            }
+            triangles.push(t);
+        }
+        Ok(triangles)
+    }
+
+    // Screen height in pixels covered by a model space sphere under the
+    // current model, view and projection. Infinite once the camera is inside.
+    pub fn projected_diameter(&self, center: &Vector3<f32>, radius: f32) -> f32 {
+        let scale = (0..3)
+            .map(|j| self.model.fixed_view::<3, 1>(0, j).norm())
+            .fold(0., f32::max);
+        let view_pos = self.view * self.model * center.push(1.);
+        let w = (self.projection * view_pos).w;
+        let radius = radius * scale;
+        if w <= 0. || view_pos.xyz().norm() <= radius {
+            return f32::INFINITY;
+        }
+        radius * self.projection[(1, 1)].abs() * self.height as f32 / w
+    }
+
+    // Draws the level of detail matching the current projected size of the group
+    pub fn draw_lod(&mut self, group: &mut LodGroup) -> Result<(), String> {
+        let level = self.select_lod(group)?;
+        let level = &group.levels()[level];
+        let (pos, ind, col) = (
+            level.positions.clone(),
+            level.indices.clone(),
+            level.colors.clone(),
+        );
+        self.draw(&pos, &ind, &col, Primitive::Triangle)
+    }
+
+    // Same selection as `draw_lod`, but draws `triangles[level]` instead of
+    // the level buffers, for meshes carrying more than positions and colors
+    pub fn draw_lod_triangles(
+        &mut self,
+        group: &mut LodGroup,
+        triangles: &[Vec<Triangle>],
+    ) -> Result<(), String> {
+        let level = self.select_lod(group)?;
+        let triangles = triangles
+            .get(level)
+            .ok_or("No triangles for the detail level")?;
+        self.draw_triangles(triangles);
+        Ok(())
+    }
+
+    fn select_lod(&self, group: &mut LodGroup) -> Result<usize, String> {
+        let pixels = self.projected_diameter(&group.center, group.radius);
+        group.select(pixels).ok_or("No detail levels")?;
+        Ok(group.current())
+    }
+
+    // Draws model space triangles with their per-vertex attributes
+    pub fn draw_triangles(&mut self, triangles: &[Triangle]) {
+        let mv = self.view * self.model;
+        let mvp = self.projection * mv;
+        let normal_mv = mv
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+            .transpose();
+
+        for t in triangles {
+            // parts behind the near plane would divide by w <= 0 below
+            let clipped = clip_near(t, &mvp);
+            for t in clipped.as_deref().unwrap_or(std::slice::from_ref(t)) {
+                let view_pos = t.v().map(|p| (mv * p.push(1.)).xyz());
+
+                let clip: Vec<Vector4<f32>> = t.v().iter().map(|p| mvp * p.push(1.)).collect();
+                let mut v: Vec<Vector4<f32>> = clip.iter().map(|c| c / c.w).collect();
+                for vert in &mut v {
+                    vert.x = 0.5 * self.width as f32 * (vert.x + 1.);
+                    vert.y = 0.5 * self.height as f32 * (vert.y + 1.);
+                    vert.z = viewport_depth(vert.z);
+                }
+
+                let mut nt = t.clone();
+                for (j, vert) in v.iter().enumerate() {
+                    nt.set_vertex(j, Vector3::new(vert.x, vert.y, vert.z)).ok();
+                    nt.set_w(j, clip[j].w).ok();
+                    let n = (normal_mv * t.normal()[j].push(0.)).xyz().normalize();
+                    nt.set_normal(j, n).ok();
+                    // tangents lie in the surface and follow the model-view itself
+                    let tangent = t.tangent()[j];
+                    let tv = (mv * tangent.xyz().push(0.)).xyz();
+                    nt.set_tangent(j, tv.push(tangent.w)).ok();
+                }
 
-                    let mut t = Triangle::default();
-                    for (j, vert) in v.iter().enumerate() {
-                        t.set_vertex(j, Vector3::new(vert.x, vert.y, vert.z)).ok();
+                match self.render_mode {
+                    RenderMode::Forward if self.fragment_shader.is_some() => {
+                        self.rasterize_shaded_triangle(&nt, &view_pos)
                     }
+                    RenderMode::Forward => self.rasterize_triangle(&nt),
+                    RenderMode::Deferred => self.rasterize_gbuffer(&nt, &view_pos),
+                }
+            }
+        }
+        if let RenderMode::Forward = self.render_mode {
+            self.resolve_sample();
+        }
+    }
 
-                    let col_x = col[i[0] as usize];
-                    let col_y = col[i[1] as usize];
-                    let col_z = col[i[2] as usize];
+    // Lighting pass: shades every covered pixel of the G-buffer once for all
+    // lights. Lights are given in world space, G-buffer samples are moved
+    // there so shadow maps can be looked up directly.
+    pub fn shade_deferred(&mut self, lights: &[Light]) {
+        let inv_view = self.view.try_inverse().unwrap_or_else(Matrix4::identity);
+        let eye_pos = inv_view.column(3).xyz();
+        let occlusion = self
+            .ssao
+            .as_ref()
+            .map(|ssao| ssao.compute(&self.gbuffer, &self.depth_buffer(), &self.projection));
 
-                    t.set_color(0, col_x[0], col_x[1], col_x[2]).ok();
-                    t.set_color(1, col_y[0], col_y[1], col_y[2]).ok();
-                    t.set_color(2, col_z[0], col_z[1], col_z[2]).ok();
-                    //self.rasterize_wireframe(&t);
-                    self.rasterize_triangle(&t);
+        for ind in 0..self.width * self.height {
+            if self.depth_buf[ind][0] == f32::INFINITY {
+                continue;
+            }
+            let point = (inv_view * self.gbuffer.position[ind].push(1.)).xyz();
+            let normal = (self.view.transpose() * self.gbuffer.normal[ind].push(0.))
+                .xyz()
+                .normalize();
+            let kd = self.gbuffer.albedo[ind];
+            let material = self
+                .materials
+                .get(self.gbuffer.material_id[ind])
+                .copied()
+                .unwrap_or_default();
+
+            let mut color = material.ka.component_mul(&self.ambient_light);
+            if let Some(occlusion) = &occlusion {
+                color *= occlusion[ind];
+            }
+            for light in lights {
+                color += light.blinn_phong(&point, &normal, &eye_pos, &kd, &material);
+            }
+            let color = color * 255.;
+            self.frame_buf[ind] = color;
+            self.sample_frame_buf[ind].fill(color);
+        }
+    }
+
+    // Fills every sample the geometry left untouched (infinite depth) with
+    // the environment seen through it. Call after drawing and shading.
+    pub fn draw_skybox(&mut self, environment: &EnvironmentMap) {
+        let inv_projection = self
+            .projection
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let view_to_world = self.view.fixed_view::<3, 3>(0, 0).transpose();
+
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let ind = (self.height - y as usize - 1) * self.width + x as usize;
+                for (j, sample) in self.get_samples(x, y).iter().enumerate() {
+                    // the G-buffer only covers the first sample
+                    let depth = match self.render_mode {
+                        RenderMode::Forward => self.depth_buf[ind][j],
+                        RenderMode::Deferred => self.depth_buf[ind][0],
+                    };
+                    if depth != f32::INFINITY {
+                        continue;
+                    }
+                    let ndc = Vector4::new(
+                        sample.x / self.width as f32 * 2. - 1.,
+                        sample.y / self.height as f32 * 2. - 1.,
+                        1.,
+                        1.,
+                    );
+                    let p = inv_projection * ndc;
+                    let dir = view_to_world * (p.xyz() / p.w).normalize();
+                    self.sample_frame_buf[ind][j] = environment.radiance(&dir) * 255.;
                 }
-                self.resolve_sample();
-                Ok(())
             }
-            _ => Err("Not supported primitive".to_string()),
         }
+        self.resolve_sample();
+    }
+
+    // Nearest depth of every pixel, row-major from the top row
+    pub fn depth_buffer(&self) -> Vec<f32> {
+        self.depth_buf
+            .iter()
+            .map(|samples| samples.iter().fold(f32::INFINITY, |acc, &d| acc.min(d)))
+            .collect()
+    }
+
+    pub fn gbuffer(&self) -> &GBuffer {
+        &self.gbuffer
     }
 
     pub fn resolve_sample(&mut self) {
@@ -325,13 +711,20 @@ impl Rasterizer {
         self.width
     }
 
-    fn draw_line(&mut self, begin: &Vector3<f32>, end: &Vector3<f32>) {
+    // Bresenham line into the first sample, clipped to the screen and depth
+    // tested with z interpolated between the endpoints. Call `resolve_sample`
+    // to show it.
+    pub fn draw_line(&mut self, begin: &Vector3<f32>, end: &Vector3<f32>, color: &Vector3<f32>) {
+        let Some((begin, end)) = clip_line(begin, end, self.width as f32, self.height as f32)
+        else {
+            return;
+        };
         let x1 = begin.x;
         let y1 = begin.y;
         let x2 = end.x;
         let y2 = end.y;
 
-        let line_color = Vector3::new(255., 255., 255.);
+        let line_color = *color;
 
         let dx = x2 - x1;
         let dy = y2 - y1;
@@ -355,7 +748,16 @@ impl Rasterizer {
                 y = y2 as i32;
                 xe = x1 as i32;
             }
-            let point = Vector3::new(x as f32, y as f32, 1.);
+            let (z_start, z_end) = if dx >= 0. {
+                (begin.z, end.z)
+            } else {
+                (end.z, begin.z)
+            };
+            let x_start = x;
+            let depth = |x: i32| {
+                z_start + (z_end - z_start) * (x - x_start) as f32 / (xe - x_start).max(1) as f32
+            };
+            let point = Vector3::new(x as f32, y as f32, z_start);
             self.set_pixel(&point, 0, &line_color);
             let xs = x + 1;
             for x in xs..=xe {
@@ -369,7 +771,7 @@ impl Rasterizer {
                     }
                     px += 2. * (dy1 - dx1);
                 }
-                let point = Vector3::new(x as f32, y as f32, 1.);
+                let point = Vector3::new(x as f32, y as f32, depth(x));
                 self.set_pixel(&point, 0, &line_color);
             }
         } else {
@@ -382,7 +784,16 @@ impl Rasterizer {
                 y = y2 as i32;
                 ye = y1 as i32;
             }
-            let point = Vector3::new(x as f32, y as f32, 1.);
+            let (z_start, z_end) = if dy >= 0. {
+                (begin.z, end.z)
+            } else {
+                (end.z, begin.z)
+            };
+            let y_start = y;
+            let depth = |y: i32| {
+                z_start + (z_end - z_start) * (y - y_start) as f32 / (ye - y_start).max(1) as f32
+            };
+            let point = Vector3::new(x as f32, y as f32, z_start);
             self.set_pixel(&point, 0, &line_color);
             let ys = y + 1;
             for y in ys..=ye {
@@ -396,16 +807,101 @@ impl Rasterizer {
                     }
                     py += 2. * (dx1 - dy1);
                 }
-                let point = Vector3::new(x as f32, y as f32, 1.);
+                let point = Vector3::new(x as f32, y as f32, depth(y));
                 self.set_pixel(&point, 0, &line_color);
             }
         }
     }
 
+    // Anti-aliased polyline in screen space, coverage falls off with the
+    // distance of each pixel center to the closest segment. Drawn over the
+    // frame buffer without depth test.
+    pub fn draw_curve(&mut self, points: &[Vector2<f32>], color: &Vector3<f32>, width: f32) {
+        let radius = width * 0.5;
+        let segments: Vec<(Vector2<f32>, Vector2<f32>)> = match points {
+            [p] => vec![(*p, *p)],
+            _ => points.windows(2).map(|s| (s[0], s[1])).collect(),
+        };
+
+        // coverage is only tracked inside the curve's bounding box
+        let (mut min, mut max) = (Vector2::repeat(f32::MAX), Vector2::repeat(f32::MIN));
+        for p in points {
+            min = min.inf(p);
+            max = max.sup(p);
+        }
+        let left = (min.x - radius - 1.).floor().max(0.) as usize;
+        let right = ((max.x + radius + 1.).ceil().max(0.) as usize).min(self.width);
+        let bottom = (min.y - radius - 1.).floor().max(0.) as usize;
+        let top = ((max.y + radius + 1.).ceil().max(0.) as usize).min(self.height);
+        if left >= right || bottom >= top {
+            return;
+        }
+        let box_width = right - left;
+        let mut coverage = vec![0f32; box_width * (top - bottom)];
+        for (a, b) in segments {
+            let x0 = (a.x.min(b.x) - radius - 1.).floor().max(left as f32) as usize;
+            let x1 = ((a.x.max(b.x) + radius + 1.).ceil().max(0.) as usize).min(right);
+            let y0 = (a.y.min(b.y) - radius - 1.).floor().max(bottom as f32) as usize;
+            let y1 = ((a.y.max(b.y) + radius + 1.).ceil().max(0.) as usize).min(top);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let center = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let d = distance_to_segment(&center, &a, &b);
+                    let ind = (y - bottom) * box_width + x - left;
+                    coverage[ind] = coverage[ind].max((radius + 0.5 - d).clamp(0., 1.));
+                }
+            }
+        }
+
+        for (i, c) in coverage.into_iter().enumerate() {
+            if c > 0. {
+                let (x, y) = (left + i % box_width, bottom + i / box_width);
+                let ind = (self.height - y - 1) * self.width + x;
+                let blended = self.frame_buf[ind].lerp(color, c);
+                self.frame_buf[ind] = blended;
+                self.sample_frame_buf[ind].fill(blended);
+            }
+        }
+    }
+
+    // Model space line segments between `positions`, projected with the
+    // current model, view and projection. Segments reaching behind the
+    // camera are skipped.
+    pub fn draw_lines(
+        &mut self,
+        positions: &[Vector3<f32>],
+        lines: &[[usize; 2]],
+        color: &Vector3<f32>,
+    ) {
+        let mvp = self.projection * self.view * self.model;
+        let screen: Vec<Option<Vector3<f32>>> = positions
+            .iter()
+            .map(|p| {
+                let clip = mvp * p.push(1.);
+                (clip.w > 0.).then(|| {
+                    let ndc = clip.xyz() / clip.w;
+                    Vector3::new(
+                        0.5 * self.width as f32 * (ndc.x + 1.),
+                        0.5 * self.height as f32 * (ndc.y + 1.),
+                        viewport_depth(ndc.z),
+                    )
+                })
+            })
+            .collect();
+
+        for &[a, b] in lines {
+            if let (Some(Some(begin)), Some(Some(end))) = (screen.get(a), screen.get(b)) {
+                self.draw_line(begin, end, color);
+            }
+        }
+        self.resolve_sample();
+    }
+
     fn rasterize_wireframe(&mut self, t: &Triangle) {
-        self.draw_line(t.c(), t.a());
-        self.draw_line(t.c(), t.b());
-        self.draw_line(t.b(), t.a());
+        let white = Vector3::new(255., 255., 255.);
+        self.draw_line(t.c(), t.a(), &white);
+        self.draw_line(t.c(), t.b(), &white);
+        self.draw_line(t.b(), t.a(), &white);
     }
 
     fn get_samples(&self, x: i32, y: i32) -> Vec<Vector3<f32>> {
@@ -427,29 +923,34 @@ impl Rasterizer {
 
     fn rasterize_triangle(&mut self, t: &Triangle) {
         // get bound box
-        let v = t.to_vector4();
         let right = t.a()[0].max(t.b()[0]).max(t.c()[0]);
         let left = t.a()[0].min(t.b()[0]).min(t.c()[0]);
         let top = t.a()[1].max(t.b()[1]).max(t.c()[1]);
         let bottom = t.a()[1].min(t.b()[1]).min(t.c()[1]);
+
+        let left = (left as i32).max(0);
+        let right = (right as i32).min(self.width as i32 - 1);
+        let bottom = (bottom as i32).max(0);
+        let top = (top as i32).min(self.height as i32 - 1);
         // For each pixel
-        for x in left as i32..right as i32 {
-            for y in bottom as i32..top as i32 {
+        for x in left..=right {
+            for y in bottom..=top {
                 // For each sample
                 let samples = self.get_samples(x, y);
                 for (j, sample) in samples.iter().enumerate() {
                     if t.contains(sample.x, sample.y) {
-                        let (alpha, beta, gamma) =
-                            compute_barycentric_2d(x as f32 + 0.5, y as f32 + 0.5, t.v());
-                        let w_reciprocal = 1.0 / (alpha / v[0].w + beta / v[1].w + gamma / v[2].w);
-                        let mut z_interpolated = alpha * v[0].z / v[0].w
-                            + beta * v[1].z / v[1].w
-                            + gamma * v[2].z / v[2].w;
-                        z_interpolated *= w_reciprocal;
+                        let (z_interpolated, [alpha, beta, gamma]) =
+                            self.barycentric_weights(t, x as f32 + 0.5, y as f32 + 0.5);
+                        // nothing is shaded here, so Gouraud and Phong both come
+                        // down to interpolating the vertex colors
+                        let color = match self.interpolation {
+                            ShadingInterpolation::Flat => t.get_color(),
+                            _ => interpolate(alpha, beta, gamma, t.color(), 1.) * 255.,
+                        };
                         self.set_pixel(
                             &Vector3::new(x as f32, y as f32, z_interpolated),
                             j,
-                            &t.get_color(),
+                            &color,
                         );
                     }
                 }
@@ -457,6 +958,162 @@ impl Rasterizer {
         }
     }
 
+    // Interpolates all attributes at the pixel center and runs the fragment
+    // shader, the result is written to every covered sample
+    fn rasterize_shaded_triangle(&mut self, t: &Triangle, view_pos: &[Vector3<f32>; 3]) {
+        let right = t.a()[0].max(t.b()[0]).max(t.c()[0]);
+        let left = t.a()[0].min(t.b()[0]).min(t.c()[0]);
+        let top = t.a()[1].max(t.b()[1]).max(t.c()[1]);
+        let bottom = t.a()[1].min(t.b()[1]).min(t.c()[1]);
+
+        let left = (left as i32).max(0);
+        let right = (right as i32).min(self.width as i32 - 1);
+        let bottom = (bottom as i32).max(0);
+        let top = (top as i32).min(self.height as i32 - 1);
+
+        let flat = matches!(self.interpolation, ShadingInterpolation::Flat);
+        let flat_normal = face_normal(view_pos, &t.normal()[0]);
+        let vertex_colors = match self.interpolation {
+            ShadingInterpolation::Gouraud => {
+                Some([0, 1, 2].map(|i| self.shade_vertex(t, view_pos, i)))
+            }
+            _ => None,
+        };
+        for x in left..=right {
+            for y in bottom..=top {
+                let ind = (self.height - y as usize - 1) * self.width + x as usize;
+                let covered: Vec<usize> = self
+                    .get_samples(x, y)
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, sample)| t.contains(sample.x, sample.y))
+                    .map(|(j, _)| j)
+                    .collect();
+                if covered.is_empty() {
+                    continue;
+                }
+
+                let (z_interpolated, [alpha, beta, gamma]) =
+                    self.barycentric_weights(t, x as f32 + 0.5, y as f32 + 0.5);
+                if covered
+                    .iter()
+                    .all(|&j| self.depth_buf[ind][j] <= z_interpolated)
+                {
+                    continue;
+                }
+
+                let pixel_color = if let Some(c) = &vertex_colors {
+                    interpolate(alpha, beta, gamma, c, 1.)
+                } else {
+                    let (color, normal) = if flat {
+                        (t.color()[0], flat_normal)
+                    } else {
+                        (
+                            interpolate(alpha, beta, gamma, t.color(), 1.),
+                            interpolate(alpha, beta, gamma, t.normal(), 1.).normalize(),
+                        )
+                    };
+                    let tex_coords = interpolate_vec2(alpha, beta, gamma, t.tex_coords(), 1.);
+                    let tangent = interpolate_vec4(alpha, beta, gamma, t.tangent(), 1.);
+                    let shading_point = interpolate(alpha, beta, gamma, view_pos, 1.);
+
+                    let mut payload = FragmentShaderPayload::new(
+                        &color,
+                        &normal,
+                        &tex_coords,
+                        self.texture.as_ref(),
+                    );
+                    payload.view_pos = shading_point;
+                    payload.tangent = tangent;
+                    match &self.fragment_shader {
+                        Some(shader) => shader(&payload),
+                        None => color * 255.,
+                    }
+                };
+
+                let point = Vector3::new(x as f32, y as f32, z_interpolated);
+                for j in covered {
+                    self.set_pixel(&point, j, &pixel_color);
+                }
+            }
+        }
+    }
+
+    // Runs the fragment shader with the attributes of vertex `i`
+    fn shade_vertex(&self, t: &Triangle, view_pos: &[Vector3<f32>; 3], i: usize) -> Vector3<f32> {
+        let mut payload = FragmentShaderPayload::new(
+            &t.color()[i],
+            &t.normal()[i],
+            &t.tex_coords()[i],
+            self.texture.as_ref(),
+        );
+        payload.view_pos = view_pos[i];
+        payload.tangent = t.tangent()[i];
+        match &self.fragment_shader {
+            Some(shader) => shader(&payload),
+            None => t.color()[i] * 255.,
+        }
+    }
+
+    // Geometry pass, one sample per pixel at the pixel center
+    fn rasterize_gbuffer(&mut self, t: &Triangle, view_pos: &[Vector3<f32>; 3]) {
+        let right = t.a()[0].max(t.b()[0]).max(t.c()[0]);
+        let left = t.a()[0].min(t.b()[0]).min(t.c()[0]);
+        let top = t.a()[1].max(t.b()[1]).max(t.c()[1]);
+        let bottom = t.a()[1].min(t.b()[1]).min(t.c()[1]);
+
+        let left = (left as i32).max(0);
+        let right = (right as i32).min(self.width as i32 - 1);
+        let bottom = (bottom as i32).max(0);
+        let top = (top as i32).min(self.height as i32 - 1);
+        for x in left..=right {
+            for y in bottom..=top {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                if !t.contains(cx, cy) {
+                    continue;
+                }
+                let (z_interpolated, [alpha, beta, gamma]) = self.barycentric_weights(t, cx, cy);
+
+                let ind = (self.height - y as usize - 1) * self.width + x as usize;
+                if self.depth_buf[ind][0] <= z_interpolated {
+                    continue;
+                }
+                self.depth_buf[ind][0] = z_interpolated;
+
+                // lighting happens later per pixel, Gouraud falls back to Phong
+                let position = interpolate(alpha, beta, gamma, view_pos, 1.);
+                let (normal, albedo) = match self.interpolation {
+                    ShadingInterpolation::Flat => {
+                        (face_normal(view_pos, &t.normal()[0]), t.color()[0])
+                    }
+                    _ => (
+                        interpolate(alpha, beta, gamma, t.normal(), 1.).normalize(),
+                        interpolate(alpha, beta, gamma, t.color(), 1.),
+                    ),
+                };
+                self.gbuffer
+                    .write(ind, position, normal, albedo, self.material_id);
+            }
+        }
+    }
+
+    // Depth and attribute weights at screen position (x, y). Depth after the
+    // perspective divide is linear in screen space and uses the plain
+    // barycentrics, attributes are linear in view space so their weights are
+    // divided by the clip space w of each vertex and renormalized.
+    fn barycentric_weights(&self, t: &Triangle, x: f32, y: f32) -> (f32, [f32; 3]) {
+        let (alpha, beta, gamma) = compute_barycentric_2d(x, y, t.v());
+        let v = t.v();
+        let z = alpha * v[0].z + beta * v[1].z + gamma * v[2].z;
+        if !self.perspective_correct {
+            return (z, [alpha, beta, gamma]);
+        }
+        let w = t.w();
+        let weights = [alpha / w[0], beta / w[1], gamma / w[2]];
+        let w_reciprocal = 1. / (weights[0] + weights[1] + weights[2]);
+        (z, weights.map(|k| k * w_reciprocal))
+    }
+
     fn get_next_id(&mut self) -> usize {
         let id = self.next_id;
         self.next_id += 1;
@@ -1,23 +1,135 @@
+#![allow(dead_code)]
+
+use crate::light::{PhongMaterial, PointLight};
 use crate::texture::Texture;
-use nalgebra::{Vector2, Vector3};
+use nalgebra::{Matrix3, Vector2, Vector3, Vector4};
+
+pub type FragmentShader = Box<dyn Fn(&FragmentShaderPayload) -> Vector3<f32>>;
 
 #[derive(Default, Debug)]
-pub struct FragmentShaderPayload {
+pub struct FragmentShaderPayload<'a> {
     pub view_pos: Vector3<f32>,
     pub color: Vector3<f32>,
     pub normal: Vector3<f32>,
+    // xyz tangent, w is the handedness of the bitangent
+    pub tangent: Vector4<f32>,
     pub tex_coords: Vector2<f32>,
-    pub texture: Option<Texture>,
+    pub texture: Option<&'a Texture>,
 }
 
-impl FragmentShaderPayload {
-    fn new(col: &Vector3<f32>, nor: &Vector3<f32>, tc: &Vector2<f32>, tex: Texture) -> Self {
+impl<'a> FragmentShaderPayload<'a> {
+    pub fn new(
+        col: &Vector3<f32>,
+        nor: &Vector3<f32>,
+        tc: &Vector2<f32>,
+        tex: Option<&'a Texture>,
+    ) -> Self {
         Self {
             color: *col,
             normal: *nor,
+            tangent: Vector4::default(),
             tex_coords: *tc,
-            texture: Some(tex),
+            texture: tex,
             view_pos: Vector3::default(),
         }
     }
 }
+
+// Scene lighting shared by the built-in shaders, in view space
+const AMB_LIGHT_INTENSITY: Vector3<f32> = Vector3::new(10., 10., 10.);
+
+fn lights() -> [PointLight; 2] {
+    [
+        PointLight::new(Vector3::new(20., 20., 20.), Vector3::new(500., 500., 500.)),
+        PointLight::new(Vector3::new(-20., 20., 0.), Vector3::new(500., 500., 500.)),
+    ]
+}
+
+fn blinn_phong(point: &Vector3<f32>, normal: &Vector3<f32>, kd: &Vector3<f32>) -> Vector3<f32> {
+    let material = PhongMaterial::default();
+    let mut result_color = material.ka.component_mul(&AMB_LIGHT_INTENSITY);
+    for light in lights() {
+        // the eye sits at the view space origin
+        result_color += light.blinn_phong(point, normal, &Vector3::zeros(), kd, &material);
+    }
+    result_color
+}
+
+// Tangent frame built from the normal alone, as in the GAMES101 framework
+fn tbn(normal: &Vector3<f32>) -> Matrix3<f32> {
+    let (x, y, z) = (normal.x, normal.y, normal.z);
+    let xz = (x * x + z * z).sqrt();
+    let t = if xz > f32::EPSILON {
+        Vector3::new(x * y / xz, xz, z * y / xz)
+    } else {
+        Vector3::x()
+    };
+    let b = normal.cross(&t);
+    Matrix3::from_columns(&[t, b, *normal])
+}
+
+fn height(texture: &Texture, u: f32, v: f32) -> f32 {
+    texture.get_color(u, v).norm()
+}
+
+// Normal perturbed by the finite differences of the height map in `texture`,
+// returned with the height at the shading point
+fn perturb_normal(payload: &FragmentShaderPayload, kh: f32, kn: f32) -> (Vector3<f32>, f32) {
+    let normal = payload.normal;
+    let Some(texture) = payload.texture else {
+        return (normal, 0.);
+    };
+    let (u, v) = (payload.tex_coords.x, payload.tex_coords.y);
+    let w = texture.width as f32;
+    let h = texture.height as f32;
+
+    let h_uv = height(texture, u, v);
+    let du = kh * kn * (height(texture, u + 1. / w, v) - h_uv);
+    let dv = kh * kn * (height(texture, u, v + 1. / h) - h_uv);
+    let ln = Vector3::new(-du, -dv, 1.);
+    ((tbn(&normal) * ln).normalize(), h_uv)
+}
+
+pub fn normal_fragment_shader(payload: &FragmentShaderPayload) -> Vector3<f32> {
+    (payload.normal.normalize() + Vector3::new(1., 1., 1.)) / 2. * 255.
+}
+
+pub fn phong_fragment_shader(payload: &FragmentShaderPayload) -> Vector3<f32> {
+    blinn_phong(&payload.view_pos, &payload.normal, &payload.color) * 255.
+}
+
+// Shades with the normal perturbed by the height map bound as texture
+pub fn bump_fragment_shader(payload: &FragmentShaderPayload) -> Vector3<f32> {
+    let (normal, _) = perturb_normal(payload, 0.2, 0.1);
+    blinn_phong(&payload.view_pos, &normal, &payload.color) * 255.
+}
+
+// Moves the shading point along the normal by the height map bound as texture
+// before shading with the perturbed normal
+pub fn displacement_fragment_shader(payload: &FragmentShaderPayload) -> Vector3<f32> {
+    let kn = 0.1;
+    let (normal, h) = perturb_normal(payload, 0.2, kn);
+    let point = payload.view_pos + kn * payload.normal * h;
+    blinn_phong(&point, &normal, &payload.color) * 255.
+}
+
+// Shades with the tangent space normal map bound as texture, using the
+// interpolated per-vertex tangents (see `Mesh::generate_tangents`)
+pub fn normal_map_fragment_shader(payload: &FragmentShaderPayload) -> Vector3<f32> {
+    let n = payload.normal;
+    let Some(texture) = payload.texture else {
+        return phong_fragment_shader(payload);
+    };
+
+    let t = payload.tangent.xyz();
+    let t = (t - n * n.dot(&t))
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(|| tbn(&n).column(0).into());
+    let handedness = if payload.tangent.w < 0. { -1. } else { 1. };
+    let b = n.cross(&t) * handedness;
+
+    let (u, v) = (payload.tex_coords.x, payload.tex_coords.y);
+    let c = texture.get_color(u, v) / 255. * 2. - Vector3::new(1., 1., 1.);
+    let normal = (t * c.x + b * c.y + n * c.z).normalize();
+    blinn_phong(&payload.view_pos, &normal, &payload.color) * 255.
+}
@@ -0,0 +1,88 @@
+#![allow(dead_code)]
+
+use crate::rst::{self, AntiAliasing, Rasterizer};
+use crate::triangle::Triangle;
+use nalgebra::{Matrix4, Vector3};
+
+// Depth of the scene as seen from a light, rendered with the regular
+// rasterizer depth path
+pub struct ShadowMap {
+    size: usize,
+    depth: Vec<f32>,
+    light_vp: Matrix4<f32>,
+
+    // Depth bias facing the light, in depth buffer units. Scaled up with the
+    // slope of the surface as seen from the light.
+    pub bias: f32,
+    // PCF kernel covers (2 * radius + 1)^2 texels
+    pub pcf_radius: i32,
+}
+
+impl ShadowMap {
+    pub fn render(
+        size: usize,
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        model: Matrix4<f32>,
+        triangles: &[Triangle],
+    ) -> Self {
+        let mut r = Rasterizer::new(size, size, AntiAliasing::None);
+        r.set_model(model);
+        r.set_view(view);
+        r.set_projection(projection);
+        r.draw_triangles(triangles);
+
+        Self {
+            size,
+            depth: r.depth_buffer(),
+            light_vp: projection * view,
+            bias: 0.3,
+            pcf_radius: 1,
+        }
+    }
+
+    // Fraction of the PCF kernel that sees `world_pos`, `cos_theta` is the
+    // cosine between the surface normal and the direction to the light
+    pub fn visibility(&self, world_pos: &Vector3<f32>, cos_theta: f32) -> f32 {
+        let clip = self.light_vp * world_pos.push(1.);
+        let ndc = clip.xyz() / clip.w;
+        if ndc.x.abs() > 1. || ndc.y.abs() > 1. || ndc.z.abs() > 1. {
+            return 1.;
+        }
+
+        let x = (0.5 * self.size as f32 * (ndc.x + 1.)) as i32;
+        let y = (0.5 * self.size as f32 * (ndc.y + 1.)) as i32;
+        let depth = rst::viewport_depth(ndc.z);
+        let cos_theta = cos_theta.clamp(0.1, 1.);
+        let bias = self.bias * (1. + (1. - cos_theta * cos_theta).sqrt() / cos_theta);
+
+        let mut lit = 0;
+        let mut total = 0;
+        for dx in -self.pcf_radius..=self.pcf_radius {
+            for dy in -self.pcf_radius..=self.pcf_radius {
+                total += 1;
+                match self.sample(x + dx, y + dy) {
+                    Some(closest) if depth - bias > closest => {}
+                    _ => lit += 1,
+                }
+            }
+        }
+        lit as f32 / total as f32
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn depth(&self) -> &Vec<f32> {
+        &self.depth
+    }
+
+    fn sample(&self, x: i32, y: i32) -> Option<f32> {
+        if x < 0 || y < 0 || x >= self.size as i32 || y >= self.size as i32 {
+            return None;
+        }
+        let ind = (self.size - y as usize - 1) * self.size + x as usize;
+        Some(self.depth[ind])
+    }
+}
@@ -0,0 +1,430 @@
+#![allow(dead_code)]
+
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Boundary constraint planes count this much more than surface planes
+const BOUNDARY_WEIGHT: f64 = 1000.;
+// Collapses turning a face further than this (cosine) are rejected as folds
+const MIN_NORMAL_COS: f64 = 0.2;
+// Collapses leaving slivers behind are rejected as well
+const MIN_COMPACTNESS: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    // Collapse edges until at most this many triangles are left
+    TriangleCount(usize),
+    // Collapse edges while the quadric error stays below this
+    MaxError(f32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Simplified {
+    pub positions: Vec<Vector3<f32>>,
+    pub indices: Vec<Vector3<i32>>,
+    // Input vertex each output vertex came from, to carry over colors and UVs
+    pub vertices: Vec<usize>,
+}
+
+// Edge collapse with quadric error metrics (Garland and Heckbert 1997) on the
+// vectors given to `Rasterizer::load_positions` and `load_indices`.
+// Open boundaries are held in place by perpendicular constraint planes.
+// Vertices sharing a position with another one, as along UV seams, never move
+// so both sides of the seam stay welded.
+pub fn simplify(
+    positions: &[Vector3<f32>],
+    indices: &[Vector3<i32>],
+    target: Target,
+) -> Result<Simplified, String> {
+    let n = positions.len();
+    let mut faces: Vec<Option<[usize; 3]>> = Vec::with_capacity(indices.len());
+    for f in indices {
+        if f.iter().any(|&i| i < 0 || i as usize >= n) {
+            return Err("Invalid ind".to_string());
+        }
+        faces.push(Some([f[0] as usize, f[1] as usize, f[2] as usize]));
+    }
+
+    let points: Vec<Vector3<f64>> = positions.iter().map(|p| p.cast()).collect();
+    let mut quadrics = vec![Matrix4::<f64>::zeros(); n];
+    let mut vertex_faces = vec![Vec::new(); n];
+    let mut edge_faces: HashMap<(usize, usize), usize> = HashMap::new();
+    for (i, f) in faces.iter().enumerate() {
+        let Some(f) = f else { continue };
+        for &v in f {
+            vertex_faces[v].push(i);
+        }
+        for k in 0..3 {
+            *edge_faces
+                .entry(edge_key(f[k], f[(k + 1) % 3]))
+                .or_insert(0) += 1;
+        }
+        if let Some(q) = plane_quadric(&points, f) {
+            for &v in f {
+                quadrics[v] += q;
+            }
+        }
+    }
+
+    for f in faces.iter().flatten() {
+        let Some(normal) = face_normal(&points, f) else {
+            continue;
+        };
+        for k in 0..3 {
+            let (a, b) = (f[k], f[(k + 1) % 3]);
+            if edge_faces[&edge_key(a, b)] != 1 {
+                continue;
+            }
+            let along = points[b] - points[a];
+            if let Some(side) = along.cross(&normal).try_normalize(f64::EPSILON) {
+                let q = quadric(&side, -side.dot(&points[a]))
+                    * (BOUNDARY_WEIGHT * along.norm_squared());
+                quadrics[a] += q;
+                quadrics[b] += q;
+            }
+        }
+    }
+
+    let mut seen = HashMap::new();
+    let mut locked = vec![false; n];
+    for (i, p) in positions.iter().enumerate() {
+        let key = (p.x.to_bits(), p.y.to_bits(), p.z.to_bits());
+        if let Some(&j) = seen.get(&key) {
+            locked[i] = true;
+            locked[j] = true;
+        } else {
+            seen.insert(key, i);
+        }
+    }
+
+    let mut state = State {
+        points,
+        quadrics,
+        faces,
+        vertex_faces,
+        locked,
+        alive: vec![true; n],
+        stamps: vec![0; n],
+    };
+    let mut heap = BinaryHeap::new();
+    for &(a, b) in edge_faces.keys() {
+        if let Some(c) = state.candidate(a, b) {
+            heap.push(c);
+        }
+    }
+
+    let mut triangle_count = state.faces.iter().flatten().count();
+    while let Some(c) = heap.pop() {
+        match target {
+            Target::TriangleCount(count) if triangle_count <= count => break,
+            Target::MaxError(error) if c.cost > error as f64 => break,
+            _ => {}
+        }
+        if !state.alive[c.keep] || !state.alive[c.remove] {
+            continue;
+        }
+        if state.stamps[c.keep] != c.stamps.0 || state.stamps[c.remove] != c.stamps.1 {
+            continue;
+        }
+        if !state.can_collapse(c.keep, c.remove, &c.position) {
+            continue;
+        }
+        triangle_count -= state.collapse(c.keep, c.remove, c.position);
+        for v in state.neighbors(c.keep) {
+            if let Some(c) = state.candidate(c.keep, v) {
+                heap.push(c);
+            }
+        }
+    }
+
+    let mut remap = vec![usize::MAX; n];
+    let mut result = Simplified::default();
+    for f in state.faces.iter().flatten() {
+        let mut face = Vector3::zeros();
+        for (k, &v) in f.iter().enumerate() {
+            if remap[v] == usize::MAX {
+                remap[v] = result.positions.len();
+                result.positions.push(state.points[v].cast());
+                result.vertices.push(v);
+            }
+            face[k] = remap[v] as i32;
+        }
+        result.indices.push(face);
+    }
+    Ok(result)
+}
+
+struct State {
+    points: Vec<Vector3<f64>>,
+    quadrics: Vec<Matrix4<f64>>,
+    faces: Vec<Option<[usize; 3]>>,
+    vertex_faces: Vec<Vec<usize>>,
+    locked: Vec<bool>,
+    alive: Vec<bool>,
+    // bumped whenever a vertex changes, outdated candidates are skipped
+    stamps: Vec<usize>,
+}
+
+struct Candidate {
+    cost: f64,
+    keep: usize,
+    remove: usize,
+    position: Vector3<f64>,
+    stamps: (usize, usize),
+}
+
+impl State {
+    fn candidate(&self, a: usize, b: usize) -> Option<Candidate> {
+        // the locked end survives in place
+        let (keep, remove) = match (self.locked[a], self.locked[b]) {
+            (true, true) => return None,
+            (false, true) => (b, a),
+            _ => (a, b),
+        };
+        let q = self.quadrics[keep] + self.quadrics[remove];
+        let error = |p: &Vector3<f64>| {
+            let h = Vector4::new(p.x, p.y, p.z, 1.);
+            (h.transpose() * q * h)[0].max(0.)
+        };
+
+        let mut options = vec![self.points[keep]];
+        if !self.locked[keep] {
+            options.push(self.points[remove]);
+            options.push((self.points[keep] + self.points[remove]) / 2.);
+            let a: Matrix3<f64> = q.fixed_view::<3, 3>(0, 0).into();
+            if a.determinant().abs() > 1e-12 {
+                if let Some(inv) = a.try_inverse() {
+                    options.push(-(inv * q.fixed_view::<3, 1>(0, 3)));
+                }
+            }
+        }
+        let position = options
+            .into_iter()
+            .min_by(|p, r| error(p).total_cmp(&error(r)))?;
+        Some(Candidate {
+            cost: error(&position),
+            keep,
+            remove,
+            position,
+            stamps: (self.stamps[keep], self.stamps[remove]),
+        })
+    }
+
+    fn neighbors(&self, v: usize) -> HashSet<usize> {
+        self.vertex_faces[v]
+            .iter()
+            .filter_map(|&f| self.faces[f])
+            .flatten()
+            .filter(|&u| u != v)
+            .collect()
+    }
+
+    // Keeps the mesh manifold and stops faces from flipping over
+    fn can_collapse(&self, keep: usize, remove: usize, position: &Vector3<f64>) -> bool {
+        let shared = self.vertex_faces[keep]
+            .iter()
+            .filter(|&&f| self.faces[f].is_some_and(|face| face.contains(&remove)))
+            .count();
+        let common = self
+            .neighbors(keep)
+            .intersection(&self.neighbors(remove))
+            .count();
+        if shared == 0 || common != shared {
+            return false;
+        }
+
+        for v in [keep, remove] {
+            for &f in &self.vertex_faces[v] {
+                let Some(face) = self.faces[f] else { continue };
+                if face.contains(&keep) && face.contains(&remove) {
+                    continue;
+                }
+                let before = face_normal(&self.points, &face);
+                let mut moved = face.map(|u| self.points[u]);
+                for (k, &u) in face.iter().enumerate() {
+                    if u == v {
+                        moved[k] = *position;
+                    }
+                }
+                let cross = (moved[1] - moved[0]).cross(&(moved[2] - moved[0]));
+                let edges: f64 = (0..3)
+                    .map(|k| (moved[(k + 1) % 3] - moved[k]).norm_squared())
+                    .sum();
+                // 1 for equilateral triangles, 0 for degenerate ones
+                let compactness = 2. * 3f64.sqrt() * cross.norm() / edges.max(f64::MIN_POSITIVE);
+                if compactness < MIN_COMPACTNESS {
+                    return false;
+                }
+                if let Some(before) = before {
+                    if cross.normalize().dot(&before) < MIN_NORMAL_COS {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Returns the number of triangles removed
+    fn collapse(&mut self, keep: usize, remove: usize, position: Vector3<f64>) -> usize {
+        let mut removed = 0;
+        for f in std::mem::take(&mut self.vertex_faces[remove]) {
+            let Some(mut face) = self.faces[f] else {
+                continue;
+            };
+            if face.contains(&keep) {
+                self.faces[f] = None;
+                removed += 1;
+                continue;
+            }
+            for u in &mut face {
+                if *u == remove {
+                    *u = keep;
+                }
+            }
+            self.faces[f] = Some(face);
+            self.vertex_faces[keep].push(f);
+        }
+        let faces = &self.faces;
+        self.vertex_faces[keep].retain(|&f| faces[f].is_some());
+
+        self.points[keep] = position;
+        self.quadrics[keep] = self.quadrics[keep] + self.quadrics[remove];
+        self.alive[remove] = false;
+        self.stamps[keep] += 1;
+        removed
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed so the max-heap pops the cheapest collapse first
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn face_normal(points: &[Vector3<f64>], f: &[usize; 3]) -> Option<Vector3<f64>> {
+    let p = f.map(|v| points[v]);
+    (p[1] - p[0])
+        .cross(&(p[2] - p[0]))
+        .try_normalize(f64::EPSILON)
+}
+
+// Squared distance to the plane n.x + d = 0
+fn quadric(n: &Vector3<f64>, d: f64) -> Matrix4<f64> {
+    let p = Vector4::new(n.x, n.y, n.z, d);
+    p * p.transpose()
+}
+
+fn plane_quadric(points: &[Vector3<f64>], f: &[usize; 3]) -> Option<Matrix4<f64>> {
+    let n = face_normal(points, f)?;
+    Some(quadric(&n, -n.dot(&points[f[0]])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unit square in the z = 0 plane split into `cells`^2 quads, with a
+    // gentle bump so the interior quadrics are not all zero
+    fn grid(cells: usize) -> (Vec<Vector3<f32>>, Vec<Vector3<i32>>) {
+        let side = cells + 1;
+        let positions = (0..side * side)
+            .map(|k| {
+                let (x, y) = (
+                    (k % side) as f32 / cells as f32,
+                    (k / side) as f32 / cells as f32,
+                );
+                let z = 0.05 * (x * (1. - x) * y * (1. - y));
+                Vector3::new(x, y, z)
+            })
+            .collect();
+        let mut indices = Vec::new();
+        for j in 0..cells {
+            for i in 0..cells {
+                let k = (j * side + i) as i32;
+                let side = side as i32;
+                indices.push(Vector3::new(k, k + 1, k + side + 1));
+                indices.push(Vector3::new(k, k + side + 1, k + side));
+            }
+        }
+        (positions, indices)
+    }
+
+    fn on_border(p: &Vector3<f32>) -> bool {
+        [p.x, p.y]
+            .iter()
+            .any(|&c| c.abs() < 1e-5 || (c - 1.).abs() < 1e-5)
+    }
+
+    // Edges used by a single triangle
+    fn boundary_edges(indices: &[Vector3<i32>]) -> Vec<(usize, usize)> {
+        let mut count: HashMap<(usize, usize), usize> = HashMap::new();
+        for f in indices {
+            for k in 0..3 {
+                *count
+                    .entry(edge_key(f[k] as usize, f[(k + 1) % 3] as usize))
+                    .or_insert(0) += 1;
+            }
+        }
+        count
+            .into_iter()
+            .filter(|&(_, c)| c == 1)
+            .map(|(e, _)| e)
+            .collect()
+    }
+
+    #[test]
+    fn reaches_the_triangle_count() {
+        let (positions, indices) = grid(8);
+        for target in [64, 32, 16] {
+            let mesh = simplify(&positions, &indices, Target::TriangleCount(target)).unwrap();
+            assert_eq!(mesh.indices.len(), target);
+            assert_eq!(mesh.vertices.len(), mesh.positions.len());
+        }
+    }
+
+    #[test]
+    fn keeps_the_boundary() {
+        let (positions, indices) = grid(8);
+        let mesh = simplify(&positions, &indices, Target::TriangleCount(16)).unwrap();
+
+        // the outline still runs along the square and has its full length,
+        // its vertices may only slide along it
+        let edges = boundary_edges(&mesh.indices);
+        let mut perimeter = 0.;
+        for (a, b) in edges {
+            let (a, b) = (mesh.positions[a], mesh.positions[b]);
+            assert!(on_border(&a) && on_border(&b));
+            assert!(a.z.abs() < 1e-3 && b.z.abs() < 1e-3);
+            assert!((a.x - b.x).abs() < 1e-5 || (a.y - b.y).abs() < 1e-5);
+            perimeter += (a - b).norm();
+        }
+        assert!((perimeter - 4.).abs() < 1e-4);
+
+        // and the corners stay where they were
+        for corner in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            let corner = Vector3::new(corner.0, corner.1, 0.);
+            assert!(mesh.positions.iter().any(|p| (p - corner).norm() < 1e-3));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+
+use crate::gbuffer::GBuffer;
+use crate::random::Rng;
+use nalgebra::{Matrix4, Vector3};
+
+// Screen space ambient occlusion over the G-buffer, the result multiplies the
+// ambient term of the deferred lighting pass
+pub struct Ssao {
+    // Hemisphere radius in view space units
+    pub radius: f32,
+    // View space depth difference below which a sample does not occlude
+    pub bias: f32,
+    // Box blur over (2 * blur_radius + 1)^2 pixels, 0 disables it
+    pub blur_radius: usize,
+    // Exponent applied to the occlusion factor to darken the result
+    pub power: f32,
+
+    kernel: Vec<Vector3<f32>>,
+    noise: Vec<Vector3<f32>>,
+}
+
+impl Ssao {
+    pub fn new(kernel_size: usize, radius: f32, blur_radius: usize) -> Self {
+        let mut rng = Rng::new(kernel_size as u64);
+
+        // samples in the +z hemisphere, denser close to the origin
+        let kernel = (0..kernel_size)
+            .map(|i| {
+                let sample = Vector3::new(rng.range(-1., 1.), rng.range(-1., 1.), rng.next_f32())
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(Vector3::z);
+                let scale = i as f32 / kernel_size as f32;
+                sample * rng.next_f32() * (0.1 + 0.9 * scale * scale)
+            })
+            .collect();
+
+        // 4x4 tile of random rotations around the normal
+        let noise = (0..16)
+            .map(|_| Vector3::new(rng.range(-1., 1.), rng.range(-1., 1.), 0.))
+            .collect();
+
+        Self {
+            radius,
+            bias: 0.025,
+            blur_radius,
+            power: 1.,
+            kernel,
+            noise,
+        }
+    }
+
+    pub fn kernel_size(&self) -> usize {
+        self.kernel.len()
+    }
+
+    // Per-pixel ambient factor in [0, 1], 1 meaning unoccluded. `depth` is the
+    // rasterizer depth buffer in the same layout as the G-buffer.
+    pub fn compute(&self, gbuffer: &GBuffer, depth: &[f32], projection: &Matrix4<f32>) -> Vec<f32> {
+        let width = gbuffer.width();
+        let height = gbuffer.height();
+        let mut occlusion = vec![1.; width * height];
+
+        for row in 0..height {
+            for x in 0..width {
+                let ind = row * width + x;
+                if depth[ind] == f32::INFINITY {
+                    continue;
+                }
+                let p = gbuffer.position[ind];
+                let n = gbuffer.normal[ind];
+
+                // TBN with a per-pixel random tangent
+                let noise = self.noise[(row % 4) * 4 + x % 4];
+                let tangent = (noise - n * noise.dot(&n))
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(|| n.cross(&Vector3::x()).normalize());
+                let bitangent = n.cross(&tangent);
+
+                let mut occluded = 0.;
+                for k in &self.kernel {
+                    let sample = p + (tangent * k.x + bitangent * k.y + n * k.z) * self.radius;
+                    let clip = projection * sample.push(1.);
+                    if clip.w.abs() < f32::EPSILON {
+                        continue;
+                    }
+                    let sx = (0.5 * width as f32 * (clip.x / clip.w + 1.)) as i32;
+                    let sy = (0.5 * height as f32 * (clip.y / clip.w + 1.)) as i32;
+                    if sx < 0 || sy < 0 || sx >= width as i32 || sy >= height as i32 {
+                        continue;
+                    }
+                    let sample_ind = (height - sy as usize - 1) * width + sx as usize;
+                    if depth[sample_ind] == f32::INFINITY {
+                        continue;
+                    }
+
+                    // camera looks down -z, a larger z is closer to the eye
+                    let scene_z = gbuffer.position[sample_ind].z;
+                    if scene_z >= sample.z + self.bias {
+                        let range = (self.radius / (p.z - scene_z).abs()).min(1.);
+                        occluded += range * range * (3. - 2. * range);
+                    }
+                }
+                let factor = 1. - occluded / self.kernel.len() as f32;
+                occlusion[ind] = factor.max(0.).powf(self.power);
+            }
+        }
+
+        self.blur(&occlusion, depth, width, height)
+    }
+
+    // Box blur restricted to covered pixels, removes the noise tile pattern
+    fn blur(&self, occlusion: &[f32], depth: &[f32], width: usize, height: usize) -> Vec<f32> {
+        if self.blur_radius == 0 {
+            return occlusion.to_vec();
+        }
+        let r = self.blur_radius as i32;
+        let mut blurred = occlusion.to_vec();
+        for row in 0..height as i32 {
+            for x in 0..width as i32 {
+                let ind = (row * width as i32 + x) as usize;
+                if depth[ind] == f32::INFINITY {
+                    continue;
+                }
+                let mut sum = 0.;
+                let mut count = 0;
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let (nx, ny) = (x + dx, row + dy);
+                        if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                            continue;
+                        }
+                        let n_ind = (ny * width as i32 + nx) as usize;
+                        if depth[n_ind] != f32::INFINITY {
+                            sum += occlusion[n_ind];
+                            count += 1;
+                        }
+                    }
+                }
+                blurred[ind] = sum / count as f32;
+            }
+        }
+        blurred
+    }
+}
@@ -1,6 +1,8 @@
+#![allow(dead_code)]
+
 use nalgebra::Vector3;
 use opencv::core::MatTraitConst;
-use opencv::core::{Mat, Vec3b};
+use opencv::core::{Mat, Vec3b, Vec3f, CV_32FC3};
 use opencv::imgcodecs::imread;
 use opencv::imgcodecs::ImreadModes;
 
@@ -12,7 +14,7 @@ pub struct Texture {
 }
 
 impl Texture {
-    fn new(name: &str) -> Self {
+    pub fn new(name: &str) -> Self {
         let image = &imread(name, ImreadModes::IMREAD_COLOR_BGR.into())
             .ok()
             .unwrap();
@@ -23,10 +25,89 @@ impl Texture {
         }
     }
 
-    fn get_color(&self, u: f32, v: f32) -> Vector3<f32> {
-        let u_img = (u * self.width as f32) as i32;
-        let v_img = ((1. - v) * self.height as f32) as i32;
+    // RGB in [0, 255]
+    pub fn get_color(&self, u: f32, v: f32) -> Vector3<f32> {
+        let u_img = ((u.clamp(0., 1.) * self.width as f32) as i32).min(self.width - 1);
+        let v_img = (((1. - v.clamp(0., 1.)) * self.height as f32) as i32).min(self.height - 1);
         let color = self.image_data.at_2d::<Vec3b>(v_img, u_img).unwrap();
-        Vector3::new(color[0] as f32, color[1] as f32, color[2] as f32)
+        Vector3::new(color[2] as f32, color[1] as f32, color[0] as f32)
+    }
+}
+
+// Linear float RGB image such as a Radiance .hdr file, rows stored top first.
+// Lookups wrap horizontally, which suits equirectangular maps.
+#[derive(Debug, Clone)]
+pub struct HdrTexture {
+    data: Vec<Vector3<f32>>,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl HdrTexture {
+    pub fn new(name: &str) -> Result<Self, String> {
+        let flags = ImreadModes::IMREAD_ANYDEPTH as i32 | ImreadModes::IMREAD_ANYCOLOR as i32;
+        let image = imread(name, flags).map_err(|e| e.to_string())?;
+        if image.typ() != CV_32FC3 {
+            return Err(format!("{name} is not a 3 channel float image"));
+        }
+
+        let mut data = Vec::with_capacity((image.rows() * image.cols()) as usize);
+        for row in 0..image.rows() {
+            for col in 0..image.cols() {
+                let c = image.at_2d::<Vec3f>(row, col).map_err(|e| e.to_string())?;
+                data.push(Vector3::new(c[2], c[1], c[0]));
+            }
+        }
+        Ok(Self::from_pixels(image.cols(), image.rows(), data))
+    }
+
+    pub fn from_pixels(width: i32, height: i32, data: Vec<Vector3<f32>>) -> Self {
+        assert_eq!(data.len(), (width * height) as usize);
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+
+    pub fn texel(&self, x: i32, y: i32) -> Vector3<f32> {
+        let x = x.rem_euclid(self.width);
+        let y = y.clamp(0, self.height - 1);
+        self.data[(y * self.width + x) as usize]
+    }
+
+    // Bilinear lookup, v = 1 is the top row
+    pub fn get_color(&self, u: f32, v: f32) -> Vector3<f32> {
+        let x = u * self.width as f32 - 0.5;
+        let y = (1. - v) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (s, t) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let top = self.texel(x0, y0).lerp(&self.texel(x0 + 1, y0), s);
+        let bottom = self.texel(x0, y0 + 1).lerp(&self.texel(x0 + 1, y0 + 1), s);
+        top.lerp(&bottom, t)
+    }
+
+    // Box filtered copy at a lower resolution
+    pub fn downsample(&self, width: i32, height: i32) -> Self {
+        let sx = self.width as f32 / width as f32;
+        let sy = self.height as f32 / height as f32;
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, x1) = ((x as f32 * sx) as i32, ((x + 1) as f32 * sx).ceil() as i32);
+                let (y0, y1) = ((y as f32 * sy) as i32, ((y + 1) as f32 * sy).ceil() as i32);
+                let mut sum = Vector3::zeros();
+                for yy in y0..y1.max(y0 + 1) {
+                    for xx in x0..x1.max(x0 + 1) {
+                        sum += self.texel(xx, yy);
+                    }
+                }
+                let count = ((y1.max(y0 + 1) - y0) * (x1.max(x0 + 1) - x0)) as f32;
+                data.push(sum / count);
+            }
+        }
+        Self::from_pixels(width, height, data)
     }
 }
@@ -0,0 +1,115 @@
+#![allow(dead_code)]
+
+use nalgebra::Vector3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapping {
+    // Plain clamp to [0, 1], values above white are lost
+    Clamp,
+    Reinhard,
+    // Narkowicz's fit of the ACES reference transform
+    Aces,
+    // Hable's Uncharted 2 curve
+    Filmic,
+}
+
+impl ToneMapping {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapping::Clamp => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::Filmic,
+            ToneMapping::Filmic => ToneMapping::Clamp,
+        }
+    }
+
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.max(0.);
+        match self {
+            ToneMapping::Clamp => x.min(1.),
+            ToneMapping::Reinhard => x / (1. + x),
+            ToneMapping::Aces => {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+            }
+            ToneMapping::Filmic => {
+                let white = 11.2;
+                (hable(2. * x) / hable(white)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.5, 0.1, 0.2, 0.02, 0.3);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// Turns the linear frame buffer into displayable 8 bit pixels. The frame
+// buffer keeps the rasterizer convention of 255 meaning white.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputStage {
+    // Linear scale applied before tone mapping
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    // Encode with the sRGB transfer function, plain [0, 1] scaling otherwise
+    pub srgb: bool,
+}
+
+impl OutputStage {
+    pub fn new(tone_mapping: ToneMapping) -> Self {
+        Self {
+            exposure: 1.,
+            tone_mapping,
+            srgb: true,
+        }
+    }
+
+    // Display value in [0, 1] for a frame buffer color
+    pub fn map(&self, color: &Vector3<f32>) -> Vector3<f32> {
+        let linear = color / 255. * self.exposure;
+        let mapped = linear.map(|c| self.tone_mapping.apply(c));
+        if self.srgb {
+            linear_to_srgb(&mapped)
+        } else {
+            mapped
+        }
+    }
+
+    // Interleaved BGR bytes as expected by highgui
+    pub fn encode_bgr8(&self, framebuffer: &[Vector3<f32>]) -> Vec<u8> {
+        let mut img_data = Vec::with_capacity(framebuffer.len() * 3);
+        for pixel in framebuffer {
+            let c = self.map(pixel) * 255. + Vector3::repeat(0.5);
+            img_data.push(c.z as u8); // B
+            img_data.push(c.y as u8); // G
+            img_data.push(c.x as u8); // R
+        }
+        img_data
+    }
+}
+
+impl Default for OutputStage {
+    fn default() -> Self {
+        Self::new(ToneMapping::Clamp)
+    }
+}
+
+pub fn linear_to_srgb(c: &Vector3<f32>) -> Vector3<f32> {
+    c.map(|x| {
+        if x <= 0.0031308 {
+            12.92 * x
+        } else {
+            1.055 * x.powf(1. / 2.4) - 0.055
+        }
+    })
+}
+
+pub fn srgb_to_linear(c: &Vector3<f32>) -> Vector3<f32> {
+    c.map(|x| {
+        if x <= 0.04045 {
+            x / 12.92
+        } else {
+            ((x + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
@@ -0,0 +1,120 @@
+#![allow(dead_code)]
+
+use nalgebra::{Matrix3, Matrix4, Vector3};
+
+// Homogeneous transform builders. Angles are in degrees, cameras look down
+// -z in view space and projections map the view volume to the [-1, 1] cube.
+
+pub fn translation(offset: &Vector3<f32>) -> Matrix4<f32> {
+    let mut m = Matrix4::identity();
+    m[(0, 3)] = offset.x;
+    m[(1, 3)] = offset.y;
+    m[(2, 3)] = offset.z;
+    m
+}
+
+pub fn scale(factors: &Vector3<f32>) -> Matrix4<f32> {
+    let mut m = Matrix4::identity();
+    m[(0, 0)] = factors.x;
+    m[(1, 1)] = factors.y;
+    m[(2, 2)] = factors.z;
+    m
+}
+
+// Rodrigues' rotation formula, counter-clockwise about `axis` looking
+// against it: R = cos I + (1 - cos) n n^T + sin N, N the cross product matrix
+pub fn rotation(axis: &Vector3<f32>, angle: f32) -> Matrix4<f32> {
+    let n = axis.normalize();
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let cross = Matrix3::new(0., -n.z, n.y, n.z, 0., -n.x, -n.y, n.x, 0.);
+    let r = Matrix3::identity() * cos + n * n.transpose() * (1. - cos) + cross * sin;
+    r.to_homogeneous()
+}
+
+// Scale, then rotate, then translate
+pub fn model(
+    offset: &Vector3<f32>,
+    axis: &Vector3<f32>,
+    angle: f32,
+    factors: &Vector3<f32>,
+) -> Matrix4<f32> {
+    translation(offset) * rotation(axis, angle) * scale(factors)
+}
+
+// View matrix of a camera at `eye` looking at `target`, `up` only needs to
+// be roughly up and is replaced when parallel to the view direction
+pub fn look_at(eye: &Vector3<f32>, target: &Vector3<f32>, up: &Vector3<f32>) -> Matrix4<f32> {
+    let f = (target - eye).normalize();
+    let up = if f.dot(&up.normalize()).abs() > 0.999 {
+        if f.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::z()
+        }
+    } else {
+        *up
+    };
+    let s = f.cross(&up).normalize();
+    let u = s.cross(&f);
+    Matrix4::new(
+        s.x,
+        s.y,
+        s.z,
+        -s.dot(eye),
+        u.x,
+        u.y,
+        u.z,
+        -u.dot(eye),
+        -f.x,
+        -f.y,
+        -f.z,
+        f.dot(eye),
+        0.,
+        0.,
+        0.,
+        1.,
+    )
+}
+
+// `fov` is the vertical field of view
+pub fn perspective(fov: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+    let t = (fov.to_radians() / 2.).tan() * z_near;
+    let r = t * aspect_ratio;
+
+    let mut projection: Matrix4<f32> = Matrix4::zeros();
+    projection[(0, 0)] = z_near / r;
+    projection[(1, 1)] = z_near / t;
+    projection[(2, 2)] = -(z_far + z_near) / (z_far - z_near);
+    projection[(2, 3)] = -2. * z_far * z_near / (z_far - z_near);
+    projection[(3, 2)] = -1.;
+    projection
+}
+
+pub fn orthographic(l: f32, r: f32, b: f32, t: f32, z_near: f32, z_far: f32) -> Matrix4<f32> {
+    let mut projection: Matrix4<f32> = Matrix4::identity();
+    projection[(0, 0)] = 2. / (r - l);
+    projection[(1, 1)] = 2. / (t - b);
+    projection[(2, 2)] = -2. / (z_far - z_near);
+    projection[(0, 3)] = -(r + l) / (r - l);
+    projection[(1, 3)] = -(t + b) / (t - b);
+    projection[(2, 3)] = -(z_far + z_near) / (z_far - z_near);
+    projection
+}
+
+// Parallel projection with receding lines drawn at `angle` from the x axis,
+// shortened by `depth_scale` (1 cavalier, 0.5 cabinet). Points on the z = 0
+// plane land where the orthographic projection puts them.
+pub fn oblique(
+    (l, r): (f32, f32),
+    (b, t): (f32, f32),
+    (z_near, z_far): (f32, f32),
+    angle: f32,
+    depth_scale: f32,
+) -> Matrix4<f32> {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let mut shear = Matrix4::identity();
+    // depth along the view direction is -z
+    shear[(0, 2)] = -depth_scale * cos;
+    shear[(1, 2)] = -depth_scale * sin;
+    orthographic(l, r, b, t, z_near, z_far) * shear
+}
@@ -5,11 +5,16 @@
 use nalgebra::{Vector2, Vector3, Vector4};
 use std::ops::RangeInclusive;
 
+#[derive(Clone)]
 pub struct Triangle {
     v: [Vector3<f32>; 3],
     color: [Vector3<f32>; 3],
     tex_coords: [Vector2<f32>; 3],
     normal: [Vector3<f32>; 3],
+    // xyz tangent, w is the handedness of the bitangent
+    tangent: [Vector4<f32>; 3],
+    // clip space w of each vertex, 1 until the triangle is projected
+    w: [f32; 3],
 }
 
 impl Triangle {
@@ -19,6 +24,8 @@ impl Triangle {
             color: [Vector3::<f32>::default(); 3],
             tex_coords: [Vector2::<f32>::default(); 3],
             normal: [Vector3::<f32>::default(); 3],
+            tangent: [Vector4::<f32>::default(); 3],
+            w: [1.; 3],
         }
     }
 
@@ -36,6 +43,26 @@ impl Triangle {
         &self.v
     }
 
+    pub fn normal(&self) -> &[Vector3<f32>; 3] {
+        &self.normal
+    }
+
+    pub fn tangent(&self) -> &[Vector4<f32>; 3] {
+        &self.tangent
+    }
+
+    pub fn color(&self) -> &[Vector3<f32>; 3] {
+        &self.color
+    }
+
+    pub fn tex_coords(&self) -> &[Vector2<f32>; 3] {
+        &self.tex_coords
+    }
+
+    pub fn w(&self) -> &[f32; 3] {
+        &self.w
+    }
+
     pub fn contains(&self, x: f32, y: f32) -> bool {
         let point = Vector3::new(x, y, 1.);
         let cross_prod0 = (self.v[0] - self.v[1]).cross(&(point - self.v[1]));
@@ -54,6 +81,16 @@ impl Triangle {
         self.normal[ind] = n;
         Ok(())
     }
+    pub fn set_tangent(&mut self, ind: usize, t: Vector4<f32>) -> Result<(), String> {
+        self.check_ind(ind)?;
+        self.tangent[ind] = t;
+        Ok(())
+    }
+    pub fn set_w(&mut self, ind: usize, w: f32) -> Result<(), String> {
+        self.check_ind(ind)?;
+        self.w[ind] = w;
+        Ok(())
+    }
     pub fn set_color(&mut self, ind: usize, r: f32, g: f32, b: f32) -> Result<(), String> {
         let range = RangeInclusive::new(0., 255.);
         if !range.contains(&r) || !range.contains(&g) || !range.contains(&b) {
@@ -63,6 +100,7 @@ impl Triangle {
         Ok(())
     }
 
+    // Color of the provoking (first) vertex in [0, 255]
     pub fn get_color(&self) -> Vector3<f32> {
         let col = self.color[0] * 255.;
         return col;
@@ -74,11 +112,20 @@ impl Triangle {
         Ok(())
     }
 
-    pub fn to_vector4(&self) -> Vec<Vector4<f32>> {
-        self.v
-            .iter()
-            .map(|v| Vector4::new(v.x, v.y, v.z, 1.0))
-            .collect()
+    // Triangle whose vertex j sits at barycentric `weights[j]` of this one,
+    // with every attribute interpolated linearly
+    pub fn sub_triangle(&self, weights: &[[f32; 3]; 3]) -> Self {
+        let mut t = self.clone();
+        for (j, b) in weights.iter().enumerate() {
+            t.v[j] = self.v[0] * b[0] + self.v[1] * b[1] + self.v[2] * b[2];
+            t.color[j] = self.color[0] * b[0] + self.color[1] * b[1] + self.color[2] * b[2];
+            t.tex_coords[j] =
+                self.tex_coords[0] * b[0] + self.tex_coords[1] * b[1] + self.tex_coords[2] * b[2];
+            t.normal[j] = self.normal[0] * b[0] + self.normal[1] * b[1] + self.normal[2] * b[2];
+            t.tangent[j] = self.tangent[0] * b[0] + self.tangent[1] * b[1] + self.tangent[2] * b[2];
+            t.w[j] = self.w[0] * b[0] + self.w[1] * b[1] + self.w[2] * b[2];
+        }
+        t
     }
 
     fn check_ind(&self, ind: usize) -> Result<(), String> {